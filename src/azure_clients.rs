@@ -1,46 +1,57 @@
 pub mod azure_clients {
-    use std::fs::File;
-    use std::io::Write;
+    use std::collections::HashMap;
     use std::str::FromStr;
+    use async_trait::async_trait;
     use reqwest::header::{HeaderMap, HeaderValue};
     use reqwest::multipart;
-    use crate::{azure_ocr_key, azure_ocr_url, azure_region, azure_text_to_speech_key, azure_text_to_speech_url, azure_translator_key, azure_translator_url, UsageOptions};
-    use crate::utils::utils::{InterpretedLine, TextToSpeechLanguage, TranslationResponse};
+    use crate::UsageOptions;
+    use crate::config::config::{required, AzureConfig};
+    use crate::error::error::{check_status, AppError, AppResult};
+    use crate::providers::providers::{OcrEngine, SpeechSynthesizer, Translator};
+    use crate::utils::utils::{voice_locale, AudioCodec, InterpretedLine, SynthesizedAudio, TextToSpeechLanguage, TranslationResponse};
     use crate::utils::utils::TextToSpeechLanguage::{English, Finnish, Japanese, Swedish};
 
     pub struct AzureOcrClient {
         client: reqwest::Client,
         headers: HeaderMap,
+        url: String,
     }
 
     impl AzureOcrClient {
-        pub fn new() -> Self {
+        pub fn new(config: &AzureConfig) -> AppResult<Self> {
             let mut headers = HeaderMap::new();
             headers.insert(
                 "Ocp-Apim-Subscription-Key",
-                HeaderValue::from_str(azure_ocr_key().as_str()).unwrap(),
+                HeaderValue::from_str(required("azure.ocr_key", &config.ocr_key)?)?,
             );
 
-            Self {
+            Ok(Self {
                 client: reqwest::Client::new(),
                 headers,
-            }
+                url: required("azure.ocr_url", &config.ocr_url)?.to_string(),
+            })
         }
 
         pub async fn make_request(
             &self,
             buffer: Vec<u8>,
             usage_options: &UsageOptions,
-        ) -> Result<String, Box<dyn std::error::Error>> {
-            let part = multipart::Part::bytes(buffer).mime_str("image/jpg")?;
+        ) -> AppResult<Vec<InterpretedLine>> {
+            let mime_type = image::guess_format(&buffer)
+                .map(|format| format.to_mime_type())
+                .unwrap_or("application/octet-stream");
+            let part = multipart::Part::bytes(buffer).mime_str(mime_type)?;
             let form = multipart::Form::new().part("file", part);
 
             let response = self
                 .client
-                .post(azure_ocr_url())
+                .post(&self.url)
                 .headers(self.headers.clone())
                 .multipart(form)
                 .send()
+                .await?;
+
+            let response = check_status("Azure OCR", response)
                 .await?
                 .json::<serde_json::Value>()
                 .await?;
@@ -49,19 +60,22 @@ pub mod azure_clients {
                 println!("{}", response.to_string());
             }
 
-            let mut output = String::with_capacity(100);
             let mut interpreted_lines = Vec::with_capacity(5);
             if let Some(regions) = response["regions"].as_array() {
-                for region in regions {
+                for (block, region) in regions.iter().enumerate() {
                     for line in region["lines"].as_array().unwrap_or(&vec![]) {
-                        let mut interpreted_line =
-                            InterpretedLine::from_str(line["boundingBox"].as_str().unwrap()).unwrap();
+                        let bounding_box = line["boundingBox"].as_str().unwrap_or_default();
+                        let mut interpreted_line = InterpretedLine::from_str(bounding_box)
+                            .map_err(|_| {
+                                AppError::Parse(format!("Invalid bounding box '{}'", bounding_box))
+                            })?;
                         let words: Vec<String> = line["words"]
                             .as_array()
                             .unwrap_or(&vec![])
                             .iter()
                             .map(|w| w["text"].as_str().unwrap_or("").trim().to_string())
                             .collect();
+                        interpreted_line.block = block;
                         interpreted_line.text.push_str(&words.join(""));
                         interpreted_lines.push(interpreted_line);
                     }
@@ -70,50 +84,34 @@ pub mod azure_clients {
                 println!("No text detected.");
             }
 
-            interpreted_lines.sort();
-
-            let mut first_line_is_name = false;
-            if interpreted_lines.len() > 1 {
-                let first_line = interpreted_lines.first().unwrap();
-                let mut rest_of_the_lines = interpreted_lines.iter().skip(1);
-
-                if rest_of_the_lines.all(|line| first_line.x - line.x > 60) {
-                    first_line_is_name = true;
-                }
-            }
-
-            if first_line_is_name {
-                let name = &interpreted_lines.first().unwrap().text;
-
-                output.push_str(name.as_str());
-                output.push_str(": ");
-                interpreted_lines
-                    .iter()
-                    .skip(1)
-                    .map(|line| line.text.as_str())
-                    .for_each(|line| output.push_str(line));
-            } else {
-                interpreted_lines
-                    .iter()
-                    .map(|line| line.text.as_str())
-                    .for_each(|line| output.push_str(&line));
-            }
+            Ok(interpreted_lines)
+        }
+    }
 
-            Ok(output)
+    #[async_trait(?Send)]
+    impl OcrEngine for AzureOcrClient {
+        async fn recognize(
+            &self,
+            image: Vec<u8>,
+            usage_options: &UsageOptions,
+        ) -> AppResult<Vec<InterpretedLine>> {
+            self.make_request(image, usage_options).await
         }
     }
 
     pub struct AzureTextToSpeechClient {
         client: reqwest::Client,
         headers: HeaderMap,
+        url: String,
+        voices: HashMap<String, String>,
     }
 
     impl AzureTextToSpeechClient {
-        pub fn new() -> Self {
+        pub fn new(config: &AzureConfig, voices: &HashMap<String, String>) -> AppResult<Self> {
             let mut headers = HeaderMap::new();
             headers.insert(
                 "Ocp-Apim-Subscription-Key",
-                HeaderValue::from_str(azure_text_to_speech_key().as_str()).unwrap(),
+                HeaderValue::from_str(required("azure.text_to_speech_key", &config.text_to_speech_key)?)?,
             );
             headers.insert(
                 "Content-Type",
@@ -125,76 +123,115 @@ pub mod azure_clients {
             );
             headers.insert("User-Agent", HeaderValue::from_static("Reqwest"));
 
-            Self {
+            Ok(Self {
                 client: reqwest::Client::new(),
                 headers,
-            }
+                url: required("azure.text_to_speech_url", &config.text_to_speech_url)?.to_string(),
+                voices: voices.clone(),
+            })
         }
 
         pub async fn make_request(
             &self,
-            text: &String,
+            text: &str,
             language: TextToSpeechLanguage,
-        ) -> Result<(), Box<dyn std::error::Error>> {
-            let body = match language {
-                Japanese => format!("<speak version='1.0' xml:lang='ja-JP'><voice xml:lang='ja-JP' xml:gender='Female' name='ja-JP-NanamiNeural'>{}</voice></speak>", text),
-                English => format!("<speak version='1.0' xml:lang='en-US'><voice xml:lang='en-US' xml:gender='Female' name='en-US-AvaMultilingualNeural'>{}</voice></speak>", text),
-                Finnish => format!("<speak version='1.0' xml:lang='fi-FI'><voice xml:lang='fi-FI' xml:gender='Female' name='fi-FI-SelmaNeural'>{}</voice></speak>", text),
-                Swedish => format!("<speak version='1.0' xml:lang='fi-FI'><voice xml:lang='sv-SV' xml:gender='Female' name='sv-SV-SelmaNeural'>{}</voice></speak>", text),
-            };
+        ) -> AppResult<SynthesizedAudio> {
+            let voice = self
+                .voices
+                .get(&language.to_string())
+                .map(String::as_str)
+                .unwrap_or(match language {
+                    Japanese => "ja-JP-NanamiNeural",
+                    English => "en-US-AvaMultilingualNeural",
+                    Finnish => "fi-FI-SelmaNeural",
+                    Swedish => "sv-SE-SofieNeural",
+                });
+            let locale = escape_xml(voice_locale(voice));
+            let voice = escape_xml(voice);
+            let text = escape_xml(text);
+            let body = format!(
+                "<speak version='1.0' xml:lang='{locale}'><voice xml:lang='{locale}' name='{voice}'>{text}</voice></speak>"
+            );
 
             let response = self
                 .client
-                .post(azure_text_to_speech_url())
+                .post(&self.url)
                 .headers(self.headers.clone())
                 .body(body)
                 .send()
                 .await?;
 
-            let response_bytes = response.bytes().await?.to_vec();
+            let response = check_status("Azure Text-to-Speech", response).await?;
 
-            // Save the audio to a file
-            let mut file = File::create("output_audio.mp3").expect("Failed to create audio file");
-            let _ = file
-                .write_all(response_bytes.as_slice())
-                .expect("Failed to write to file");
+            Ok(SynthesizedAudio {
+                data: response.bytes().await?.to_vec(),
+                codec: AudioCodec::Mp3,
+                sample_rate: 16000,
+            })
+        }
+    }
+
+    /// Escapes the characters that would end or break the SSML text or attribute they are in.
+    fn escape_xml(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&apos;"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
 
-            Ok(())
+    #[async_trait(?Send)]
+    impl SpeechSynthesizer for AzureTextToSpeechClient {
+        async fn synthesize(
+            &self,
+            text: &str,
+            language: TextToSpeechLanguage,
+        ) -> AppResult<SynthesizedAudio> {
+            self.make_request(text, language).await
         }
     }
 
     pub struct AzureTranslatorClient {
         client: reqwest::Client,
         headers: HeaderMap,
+        url: String,
     }
 
     impl AzureTranslatorClient {
-        pub fn new() -> Self {
-            let azure_translator_key = azure_translator_key();
-            let azure_region = azure_region();
+        pub fn new(config: &AzureConfig) -> AppResult<Self> {
+            let azure_translator_key = required("azure.translator_key", &config.translator_key)?;
+            let azure_region = required("azure.region", &config.region)?;
 
             let mut headers = HeaderMap::new();
             headers.insert(
                 "Ocp-Apim-Subscription-Key",
-                HeaderValue::from_str(azure_translator_key.as_str()).unwrap(),
+                HeaderValue::from_str(azure_translator_key)?,
             );
             headers.insert(
                 "Ocp-Apim-Subscription-Region",
-                HeaderValue::from_str(azure_region.as_str()).unwrap(),
+                HeaderValue::from_str(azure_region)?,
             );
             headers.insert("Content-Type", HeaderValue::from_static("application/json"));
             headers.insert("User-Agent", HeaderValue::from_static("Reqwest"));
 
-            Self {
+            Ok(Self {
                 client: reqwest::Client::new(),
                 headers,
-            }
+                url: required("azure.translator_url", &config.translator_url)?.to_string(),
+            })
         }
         pub async fn make_request(
             &self,
-            text: &String,
+            text: &str,
             output_languages: &[TextToSpeechLanguage],
-        ) -> Result<TranslationResponse, Box<dyn std::error::Error>> {
+        ) -> AppResult<TranslationResponse> {
             if output_languages.is_empty() {
                 return Ok(TranslationResponse {
                     en_translation: String::new(),
@@ -203,8 +240,7 @@ pub mod azure_clients {
                 });
             }
 
-            let azure_translator_url = azure_translator_url();
-            let body = format!("[{{ \"Text\": \"{}\" }}]", text);
+            let body = serde_json::json!([{ "Text": text }]).to_string();
             let output_language = output_languages
                 .iter()
                 .map(|lang| lang.to_string())
@@ -213,10 +249,13 @@ pub mod azure_clients {
 
             let response = self
                 .client
-                .post(format!("{}&to={}", azure_translator_url, output_language))
+                .post(format!("{}&to={}", self.url, output_language))
                 .headers(self.headers.clone())
                 .body(body)
                 .send()
+                .await?;
+
+            let response = check_status("Azure Translator", response)
                 .await?
                 .json::<serde_json::Value>()
                 .await?;
@@ -247,4 +286,27 @@ pub mod azure_clients {
             Ok(translation_response)
         }
     }
-}
\ No newline at end of file
+
+    #[async_trait(?Send)]
+    impl Translator for AzureTranslatorClient {
+        async fn translate(
+            &self,
+            text: &str,
+            output_languages: &[TextToSpeechLanguage],
+        ) -> AppResult<TranslationResponse> {
+            self.make_request(text, output_languages).await
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn escapes_text_for_ssml() {
+            assert_eq!(escape_xml("Tom & Jerry <3"), "Tom &amp; Jerry &lt;3");
+            assert_eq!(escape_xml("it's \"fine\""), "it&apos;s &quot;fine&quot;");
+            assert_eq!(escape_xml("こんにちは"), "こんにちは");
+        }
+    }
+}
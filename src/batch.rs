@@ -0,0 +1,179 @@
+pub mod batch {
+    use std::path::{Path, PathBuf};
+    use futures::stream::{self, StreamExt};
+    use serde::Serialize;
+    use crate::camera_capture::camera_capture::FrameProcessor;
+    use crate::cli::cli::{print_translations, OutputFormat};
+    use crate::error::error::{with_retries, AppResult};
+    use crate::frame_source::frame_source::image_files;
+    use crate::pipeline::pipeline::Pipeline;
+    use crate::regions::regions::Region;
+    use crate::report::report::ReportRow;
+    use crate::utils::utils::{DialogueLine, TextToSpeechLanguage, TranslationResponse, UsageOptions};
+
+    /// What came out of one image. Failures are recorded here instead of stopping the batch.
+    #[derive(Serialize)]
+    pub struct BatchResult {
+        pub path: String,
+        #[serde(flatten)]
+        pub line: DialogueLine,
+        pub translations: TranslationResponse,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub error: Option<String>,
+    }
+
+    impl ReportRow for BatchResult {
+        const HEADER: &'static [&'static str] = &["path", "speaker", "text", "en", "fi", "sv", "error"];
+
+        fn record(&self) -> Vec<&str> {
+            vec![
+                self.path.as_str(),
+                self.line.speaker.as_deref().unwrap_or_default(),
+                self.line.text.as_str(),
+                self.translations.en_translation.as_str(),
+                self.translations.fi_translation.as_str(),
+                self.translations.sv_translation.as_str(),
+                self.error.as_deref().unwrap_or_default(),
+            ]
+        }
+    }
+
+    /// How the images are handled on their way to the pipeline.
+    pub struct BatchOptions<'a> {
+        pub jobs: usize,
+        pub raw: bool,
+        /// The region the images are cropped to, `None` to keep them whole
+        pub region: Option<&'a Region>,
+        pub languages: &'a [TextToSpeechLanguage],
+    }
+
+    /// Expands directories into the images they contain.
+    pub fn collect_images(inputs: &[PathBuf]) -> AppResult<Vec<PathBuf>> {
+        let mut images = Vec::new();
+
+        for input in inputs {
+            if input.is_dir() {
+                images.extend(image_files(input)?);
+            } else {
+                images.push(input.clone());
+            }
+        }
+
+        Ok(images)
+    }
+
+    /// Runs OCR and translation on every image, with at most `options.jobs` images in
+    /// flight at once. Results come back in the order of `images`.
+    pub async fn process_images(
+        images: &[PathBuf],
+        processor: &FrameProcessor,
+        pipeline: &Pipeline,
+        usage_options: &UsageOptions,
+        options: &BatchOptions<'_>,
+    ) -> Vec<BatchResult> {
+        let total = images.len();
+
+        stream::iter(images.iter().enumerate())
+            .map(|(index, path)| async move {
+                let result = process_image(path, processor, pipeline, usage_options, options).await;
+                eprintln!(
+                    "[{}/{}] {}{}",
+                    index + 1,
+                    total,
+                    path.display(),
+                    if result.error.is_some() { " failed" } else { "" }
+                );
+                result
+            })
+            .buffered(options.jobs.max(1))
+            .collect()
+            .await
+    }
+
+    async fn process_image(
+        path: &Path,
+        processor: &FrameProcessor,
+        pipeline: &Pipeline,
+        usage_options: &UsageOptions,
+        options: &BatchOptions<'_>,
+    ) -> BatchResult {
+        let mut result = BatchResult {
+            path: path.display().to_string(),
+            line: DialogueLine::default(),
+            translations: TranslationResponse::default(),
+            error: None,
+        };
+
+        let line = match recognize(path, processor, pipeline, usage_options, options).await {
+            Ok(line) => line,
+            Err(e) => {
+                result.error = Some(e.to_string());
+                return result;
+            }
+        };
+
+        // A failed translation keeps the recognized text in the report next to the error
+        if !line.text.trim().is_empty() {
+            let line = &line;
+            match with_retries(move || pipeline.translate_dialogue(line, options.languages)).await {
+                Ok(translations) => result.translations = translations,
+                Err(e) => result.error = Some(e.to_string()),
+            }
+        }
+        result.line = line;
+
+        result
+    }
+
+    /// Loads the image and runs OCR on it. Only the OCR request is retried, an image that
+    /// cannot be read fails straight away.
+    async fn recognize(
+        path: &Path,
+        processor: &FrameProcessor,
+        pipeline: &Pipeline,
+        usage_options: &UsageOptions,
+        options: &BatchOptions<'_>,
+    ) -> AppResult<DialogueLine> {
+        let image = if options.raw {
+            std::fs::read(path)?
+        } else {
+            processor.load_image_from_file(
+                path,
+                options.region,
+                usage_options,
+            )?
+        };
+
+        let image = &image;
+        with_retries(move || pipeline.recognize(image.clone(), usage_options)).await
+    }
+
+    /// Prints the results when no report file was asked for, one JSON object per line
+    /// in JSON mode.
+    pub fn print_results(results: &[BatchResult], format: OutputFormat) -> AppResult<()> {
+        for result in results {
+            match format {
+                OutputFormat::Json => println!("{}", serde_json::to_string(result)?),
+                OutputFormat::Text => {
+                    println!("== {} ==", result.path);
+                    if let Some(error) = &result.error {
+                        println!("{}\n", error);
+                        if result.line.text.is_empty() {
+                            continue;
+                        }
+                    }
+
+                    println!("{}\n", result.line);
+                    print_translations(&result.translations);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The number of images that could not be processed.
+    pub fn failures(results: &[BatchResult]) -> usize {
+        results.iter().filter(|result| result.error.is_some()).count()
+    }
+}
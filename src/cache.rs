@@ -0,0 +1,340 @@
+pub mod cache {
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+    use std::time::SystemTime;
+    use async_trait::async_trait;
+    use sha2::{Digest, Sha256};
+    use crate::config::config::CacheConfig;
+    use crate::error::error::AppResult;
+    use crate::providers::providers::{Provider, SpeechSynthesizer, Translator};
+    use crate::utils::utils::{AudioCodec, SynthesizedAudio, TextToSpeechLanguage, TranslationResponse};
+
+    /// Files on disk named after the SHA-256 of what produced them. Reading an entry
+    /// marks it as used, and the least recently used entries are removed once the
+    /// directory grows past `max_bytes`.
+    pub struct DiskCache {
+        directory: PathBuf,
+        max_bytes: u64,
+        // The size of the directory, counted once and then kept up to date by `put`
+        total_bytes: Cell<u64>,
+    }
+
+    impl DiskCache {
+        pub fn new(config: &CacheConfig) -> AppResult<Self> {
+            std::fs::create_dir_all(&config.directory)?;
+            let directory = PathBuf::from(&config.directory);
+            let total_bytes = entries(&directory)?.iter().map(|(_, size, _)| size).sum();
+
+            Ok(Self {
+                directory,
+                max_bytes: config.max_size_mb.saturating_mul(1024 * 1024),
+                total_bytes: Cell::new(total_bytes),
+            })
+        }
+
+        /// The key of the entry produced from `parts`, which are separated so that
+        /// ("ab", "c") and ("a", "bc") do not collide.
+        pub fn key(parts: &[&str]) -> String {
+            let mut hasher = Sha256::new();
+            for part in parts {
+                hasher.update(part.as_bytes());
+                hasher.update([0]);
+            }
+            format!("{:x}", hasher.finalize())
+        }
+
+        pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+            let path = self.directory.join(key);
+            let data = std::fs::read(&path).ok()?;
+
+            // The modification time records the last use, for eviction
+            if let Ok(file) = File::options().write(true).open(&path) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+
+            Some(data)
+        }
+
+        /// Stores the entry. It is written next to its final place and renamed into it, so
+        /// an interrupted write never leaves a truncated entry behind.
+        pub fn put(&self, key: &str, data: &[u8]) -> AppResult<()> {
+            let path = self.directory.join(key);
+            let temporary = self.directory.join(format!("{}.{}.tmp", key, std::process::id()));
+            let replaced = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+
+            std::fs::write(&temporary, data)?;
+            if let Err(e) = std::fs::rename(&temporary, &path) {
+                let _ = std::fs::remove_file(&temporary);
+                return Err(e.into());
+            }
+
+            let total = self.total_bytes.get().saturating_sub(replaced) + data.len() as u64;
+            self.total_bytes.set(total);
+            if total > self.max_bytes {
+                self.evict()?;
+            }
+
+            Ok(())
+        }
+
+        fn evict(&self) -> AppResult<()> {
+            let mut entries = entries(&self.directory)?;
+            let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+            entries.sort_by_key(|(modified, _, _)| *modified);
+
+            for (_, size, path) in entries {
+                if total <= self.max_bytes {
+                    break;
+                }
+                std::fs::remove_file(path)?;
+                total -= size;
+            }
+
+            self.total_bytes.set(total);
+            Ok(())
+        }
+    }
+
+    /// The files in `directory` with their last use and size.
+    fn entries(directory: &Path) -> AppResult<Vec<(SystemTime, u64, PathBuf)>> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                entries.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Serves translations from the cache and asks `inner` only for the languages that
+    /// have not been translated before.
+    pub struct CachedTranslator {
+        inner: Rc<dyn Translator>,
+        provider: Provider,
+        cache: Rc<DiskCache>,
+    }
+
+    impl CachedTranslator {
+        pub fn new(inner: Rc<dyn Translator>, provider: Provider, cache: Rc<DiskCache>) -> Self {
+            Self { inner, provider, cache }
+        }
+
+        fn key(&self, text: &str, language: TextToSpeechLanguage) -> String {
+            DiskCache::key(&["translation", &format!("{:?}", self.provider), &language.to_string(), text])
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl Translator for CachedTranslator {
+        async fn translate(
+            &self,
+            text: &str,
+            output_languages: &[TextToSpeechLanguage],
+        ) -> AppResult<TranslationResponse> {
+            let mut response = TranslationResponse::default();
+            let mut missing = Vec::new();
+
+            for &language in output_languages {
+                match self.cache.get(&self.key(text, language)).map(String::from_utf8) {
+                    Some(Ok(translation)) => response.set(language, translation),
+                    _ => missing.push(language),
+                }
+            }
+
+            if missing.is_empty() {
+                return Ok(response);
+            }
+
+            let translated = self.inner.translate(text, &missing).await?;
+            for language in missing {
+                let translation = translated.get(language);
+                if translation.is_empty() {
+                    continue;
+                }
+
+                // A full disk should not cost the translation that was already paid for
+                if let Err(e) = self.cache.put(&self.key(text, language), translation.as_bytes()) {
+                    eprintln!("Could not cache the translation: {}", e);
+                }
+                response.set(language, translation.to_string());
+            }
+
+            Ok(response)
+        }
+    }
+
+    /// Serves synthesized speech from the cache. The configured voice is part of the key,
+    /// so changing it produces new audio.
+    pub struct CachedSpeechSynthesizer {
+        inner: Rc<dyn SpeechSynthesizer>,
+        provider: Provider,
+        voices: HashMap<String, String>,
+        cache: Rc<DiskCache>,
+    }
+
+    // The header in front of cached audio: the codec, then the sample rate
+    const AUDIO_HEADER_SIZE: usize = 5;
+
+    impl CachedSpeechSynthesizer {
+        pub fn new(
+            inner: Rc<dyn SpeechSynthesizer>,
+            provider: Provider,
+            voices: &HashMap<String, String>,
+            cache: Rc<DiskCache>,
+        ) -> Self {
+            Self {
+                inner,
+                provider,
+                voices: voices.clone(),
+                cache,
+            }
+        }
+
+        fn key(&self, text: &str, language: TextToSpeechLanguage) -> String {
+            let language = language.to_string();
+            let voice = self.voices.get(&language).map_or("default", String::as_str);
+            DiskCache::key(&["speech", &format!("{:?}", self.provider), &language, voice, text])
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl SpeechSynthesizer for CachedSpeechSynthesizer {
+        async fn synthesize(
+            &self,
+            text: &str,
+            language: TextToSpeechLanguage,
+        ) -> AppResult<SynthesizedAudio> {
+            let key = self.key(text, language);
+            if let Some(audio) = self.cache.get(&key).and_then(|entry| decode_audio(&entry)) {
+                return Ok(audio);
+            }
+
+            let audio = self.inner.synthesize(text, language).await?;
+            if let Err(e) = self.cache.put(&key, &encode_audio(&audio)) {
+                eprintln!("Could not cache the speech: {}", e);
+            }
+
+            Ok(audio)
+        }
+    }
+
+    fn encode_audio(audio: &SynthesizedAudio) -> Vec<u8> {
+        let codec = match audio.codec {
+            AudioCodec::Mp3 => 0,
+        };
+
+        let mut entry = Vec::with_capacity(AUDIO_HEADER_SIZE + audio.data.len());
+        entry.push(codec);
+        entry.extend_from_slice(&audio.sample_rate.to_le_bytes());
+        entry.extend_from_slice(&audio.data);
+        entry
+    }
+
+    fn decode_audio(entry: &[u8]) -> Option<SynthesizedAudio> {
+        if entry.len() <= AUDIO_HEADER_SIZE {
+            return None;
+        }
+
+        let codec = match entry[0] {
+            0 => AudioCodec::Mp3,
+            _ => return None,
+        };
+        let sample_rate = u32::from_le_bytes(entry[1..AUDIO_HEADER_SIZE].try_into().ok()?);
+
+        Some(SynthesizedAudio {
+            data: entry[AUDIO_HEADER_SIZE..].to_vec(),
+            codec,
+            sample_rate,
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::Duration;
+
+        const ENTRY_SIZE: usize = 400 * 1024;
+
+        fn cache(name: &str) -> DiskCache {
+            let directory = std::env::temp_dir()
+                .join(format!("auto_translator-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&directory);
+
+            DiskCache::new(&CacheConfig {
+                enabled: true,
+                directory: directory.display().to_string(),
+                max_size_mb: 1,
+            })
+            .unwrap()
+        }
+
+        fn last_used(cache: &DiskCache, key: &str, seconds_ago: u64) {
+            let file = File::options().write(true).open(cache.directory.join(key)).unwrap();
+            file.set_modified(SystemTime::now() - Duration::from_secs(seconds_ago)).unwrap();
+        }
+
+        #[test]
+        fn keys_keep_their_parts_apart() {
+            assert_ne!(DiskCache::key(&["ab", "c"]), DiskCache::key(&["a", "bc"]));
+            assert_eq!(DiskCache::key(&["a", "b"]), DiskCache::key(&["a", "b"]));
+        }
+
+        #[test]
+        fn evicts_the_least_recently_used_entries() {
+            let cache = cache("eviction");
+            cache.put("first", &[1; ENTRY_SIZE]).unwrap();
+            cache.put("second", &[2; ENTRY_SIZE]).unwrap();
+            last_used(&cache, "first", 20);
+            last_used(&cache, "second", 10);
+
+            // Reading the first entry makes the second one the least recently used
+            assert_eq!(cache.get("first"), Some(vec![1; ENTRY_SIZE]));
+            cache.put("third", &[3; ENTRY_SIZE]).unwrap();
+
+            assert!(cache.get("first").is_some());
+            assert!(cache.get("second").is_none());
+            assert!(cache.get("third").is_some());
+            assert_eq!(cache.total_bytes.get(), 2 * ENTRY_SIZE as u64);
+
+            let _ = std::fs::remove_dir_all(&cache.directory);
+        }
+
+        #[test]
+        fn replacing_an_entry_does_not_count_it_twice() {
+            let cache = cache("replace");
+            cache.put("entry", &[1; ENTRY_SIZE]).unwrap();
+            cache.put("entry", &[2; ENTRY_SIZE]).unwrap();
+
+            assert_eq!(cache.total_bytes.get(), ENTRY_SIZE as u64);
+            assert_eq!(cache.get("entry"), Some(vec![2; ENTRY_SIZE]));
+
+            let _ = std::fs::remove_dir_all(&cache.directory);
+        }
+
+        #[test]
+        fn audio_survives_the_round_trip() {
+            let audio = SynthesizedAudio {
+                data: vec![0xFF, 0xFB, 0x90, 0x00],
+                codec: AudioCodec::Mp3,
+                sample_rate: 24000,
+            };
+
+            let decoded = decode_audio(&encode_audio(&audio)).unwrap();
+            assert_eq!(decoded.data, audio.data);
+            assert_eq!(decoded.codec, audio.codec);
+            assert_eq!(decoded.sample_rate, audio.sample_rate);
+        }
+
+        #[test]
+        fn rejects_damaged_audio_entries() {
+            assert!(decode_audio(&[0, 0xC0, 0x5D, 0, 0]).is_none());
+            assert!(decode_audio(&[7, 0xC0, 0x5D, 0, 0, 0xFF]).is_none());
+        }
+    }
+}
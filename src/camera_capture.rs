@@ -1,205 +1,629 @@
 pub mod camera_capture {
-    use std::fs::File;
-    use std::io::Read;
-    use opencv::core::{Mat, Rect};
+    use std::fmt::{Display, Formatter};
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+    use opencv::core::{Mat, Rect, Vector};
+    use serde::{Deserialize, Serialize};
     use opencv::prelude::{MatExprTraitConst, MatTraitConst, VideoCaptureTrait, VideoCaptureTraitConst};
     use opencv::videoio::VideoCapture;
-    use crate::threshold;
+    use crate::config::config::{CameraConfig, Config, DialogueDetectionConfig, PerspectiveConfig};
+    use crate::dialogue_detection::dialogue_detection::detect_dialogue_box;
+    use crate::error::error::{AppError, AppResult};
+    use crate::frame_source::frame_source::{read_image, FrameSource};
+    use crate::perspective::perspective::{corners_from_config, corners_to_config, detect_screen, rectify};
+    use crate::preprocessing::preprocessing::{apply_steps, PreprocessStep};
+    use crate::regions::regions::Region;
+    use crate::utils::utils::UsageOptions;
 
-    pub struct CameraCapture {
-        cap: VideoCapture,
-        height: i32,
-        width: i32,
+    const MAX_PROBED_DEVICES: i32 = 10;
+    const PROBED_RESOLUTIONS: [(i32, i32); 6] = [
+        (640, 480),
+        (1280, 720),
+        (1920, 1080),
+        (2560, 1440),
+        (3840, 2160),
+        (4096, 2160),
+    ];
+
+    /// Container format used when encoding captured frames for OCR.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ImageFormat {
+        #[serde(alias = "jpg")]
+        Jpeg,
+        Png,
+        WebP,
+    }
+
+    impl ImageFormat {
+        fn extension(&self) -> &'static str {
+            match self {
+                ImageFormat::Jpeg => ".jpg",
+                ImageFormat::Png => ".png",
+                ImageFormat::WebP => ".webp",
+            }
+        }
+    }
+
+    impl FromStr for ImageFormat {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_lowercase().as_str() {
+                "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+                "png" => Ok(ImageFormat::Png),
+                "webp" => Ok(ImageFormat::WebP),
+                other => Err(format!("Unknown image format '{}', expected jpeg, png or webp", other)),
+            }
+        }
+    }
+
+    /// How several camera frames are merged into one when `camera.frames` is above 1.
+    /// The median also drops things that are only in some frames, like a refresh line.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum FrameCombination {
+        Mean,
+        Median,
+    }
+
+    impl FromStr for FrameCombination {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_lowercase().as_str() {
+                "mean" => Ok(FrameCombination::Mean),
+                "median" => Ok(FrameCombination::Median),
+                other => Err(format!("Unknown frame combination '{}', expected mean or median", other)),
+            }
+        }
     }
 
-    impl CameraCapture {
-        pub fn new(width: i32, height: i32) -> Self {
-            let mut camera_capture = CameraCapture {
-                cap: VideoCapture::new(0, CameraCapture::get_backend()).unwrap(),
-                height,
-                width,
+    /// How captured frames are turned into bytes. `quality` is 0-100 for every format,
+    /// for PNG it is mapped onto the compression level.
+    pub struct FrameEncoding {
+        pub format: ImageFormat,
+        pub quality: i32,
+        pub debug_frame_path: Option<String>,
+    }
+
+    impl FrameEncoding {
+        /// Where the encoded frame is saved for debugging. Frames of an image file get the
+        /// file's name added, so that the images of a batch do not overwrite each other.
+        fn debug_frame_path(&self, image: Option<&Path>) -> Option<PathBuf> {
+            let path = PathBuf::from(self.debug_frame_path.as_ref()?);
+            let Some(name) = image.and_then(Path::file_stem) else {
+                return Some(path);
             };
 
-            if !camera_capture.cap.is_opened().unwrap() {
-                return camera_capture;
+            let mut file_name = path.file_stem().unwrap_or_default().to_os_string();
+            file_name.push("-");
+            file_name.push(name);
+            if let Some(extension) = path.extension() {
+                file_name.push(".");
+                file_name.push(extension);
+            }
+            Some(path.with_file_name(file_name))
+        }
+
+        fn params(&self) -> Vector<i32> {
+            let quality = self.quality.clamp(0, 100);
+            match self.format {
+                ImageFormat::Jpeg => Vector::from_slice(&[opencv::imgcodecs::IMWRITE_JPEG_QUALITY, quality]),
+                ImageFormat::Png => Vector::from_slice(&[
+                    opencv::imgcodecs::IMWRITE_PNG_COMPRESSION,
+                    (100 - quality) * 9 / 100,
+                ]),
+                ImageFormat::WebP => {
+                    Vector::from_slice(&[opencv::imgcodecs::IMWRITE_WEBP_QUALITY, quality.max(1)])
+                }
             }
+        }
+    }
+
+    /// The processing applied to every frame before OCR, whatever source it came from.
+    pub struct FrameProcessor {
+        threshold: f64,
+        encoding: FrameEncoding,
+        detection: DialogueDetectionConfig,
+        perspective: PerspectiveConfig,
+        steps: Vec<PreprocessStep>,
+    }
 
-            camera_capture
-                .cap
-                .set(opencv::videoio::CAP_PROP_FRAME_WIDTH, width as f64)
-                .unwrap();
-            camera_capture
-                .cap
-                .set(opencv::videoio::CAP_PROP_FRAME_HEIGHT, height as f64)
-                .unwrap();
+    /// The OpenCV capture API used to open the camera. `Auto` picks DirectShow on Windows
+    /// and lets OpenCV decide elsewhere.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum CameraBackend {
+        Auto,
+        Any,
+        DShow,
+        Msmf,
+        V4l2,
+        AvFoundation,
+    }
+
+    impl CameraBackend {
+        fn api_preference(&self) -> i32 {
+            match self {
+                CameraBackend::Auto if cfg!(target_os = "windows") => opencv::videoio::CAP_DSHOW,
+                CameraBackend::Auto | CameraBackend::Any => opencv::videoio::CAP_ANY,
+                CameraBackend::DShow => opencv::videoio::CAP_DSHOW,
+                CameraBackend::Msmf => opencv::videoio::CAP_MSMF,
+                CameraBackend::V4l2 => opencv::videoio::CAP_V4L2,
+                CameraBackend::AvFoundation => opencv::videoio::CAP_AVFOUNDATION,
+            }
+        }
+    }
 
-            camera_capture.height = camera_capture
-                .cap
-                .get(opencv::videoio::CAP_PROP_FRAME_HEIGHT)
-                .unwrap() as i32;
-            camera_capture.width = camera_capture
-                .cap
-                .get(opencv::videoio::CAP_PROP_FRAME_WIDTH)
-                .unwrap() as i32;
+    impl FromStr for CameraBackend {
+        type Err = String;
 
-            camera_capture
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_lowercase().as_str() {
+                "auto" => Ok(CameraBackend::Auto),
+                "any" => Ok(CameraBackend::Any),
+                "dshow" => Ok(CameraBackend::DShow),
+                "msmf" => Ok(CameraBackend::Msmf),
+                "v4l2" => Ok(CameraBackend::V4l2),
+                "avfoundation" => Ok(CameraBackend::AvFoundation),
+                other => Err(format!(
+                    "Unknown camera backend '{}', expected auto, any, dshow, msmf, v4l2 or avfoundation",
+                    other
+                )),
+            }
         }
+    }
+
+    /// A camera picked by OpenCV index, or by device path such as `/dev/video2` on Linux.
+    #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+    #[serde(untagged)]
+    pub enum CameraDevice {
+        Index(i32),
+        Path(String),
+    }
 
-        pub fn capture_image(
-            &mut self,
-            half_screen: bool,
-            color_correction: bool,
-        ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    impl FromStr for CameraDevice {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim() {
+                "" => Err("Camera device cannot be empty".to_string()),
+                device => Ok(device
+                    .parse()
+                    .map(CameraDevice::Index)
+                    .unwrap_or_else(|_| CameraDevice::Path(device.to_string()))),
+            }
+        }
+    }
+
+    impl Display for CameraDevice {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                CameraDevice::Index(index) => write!(f, "camera {}", index),
+                CameraDevice::Path(path) => f.write_str(path),
+            }
+        }
+    }
+
+    impl CameraDevice {
+        fn open(&self, backend: CameraBackend) -> AppResult<VideoCapture> {
+            Ok(match self {
+                CameraDevice::Index(index) => VideoCapture::new(*index, backend.api_preference())?,
+                CameraDevice::Path(path) => VideoCapture::from_file(path, backend.api_preference())?,
+            })
+        }
+    }
+
+    /// A live camera, e.g. a capture card showing the game.
+    pub struct CameraSource {
+        cap: VideoCapture,
+        device: CameraDevice,
+        frames: u32,
+        stale_frames: u32,
+        combination: FrameCombination,
+    }
+
+    impl CameraSource {
+        /// Opens the configured camera at the configured resolution, failing when either is
+        /// not possible instead of capturing from the wrong device or at the wrong size.
+        pub fn new(config: &CameraConfig) -> AppResult<Self> {
+            let mut cap = config.device.open(config.backend)?;
+            if !cap.is_opened()? {
+                return Err(AppError::Camera(format!(
+                    "Could not open {} with the {:?} backend",
+                    config.device, config.backend
+                )));
+            }
+
+            let (width, height) = set_resolution(&mut cap, config.width, config.height)?;
+            if (width, height) != (config.width, config.height) {
+                return Err(AppError::Camera(format!(
+                    "{} does not support {}x{}, it opened at {}x{}; list-cameras shows the resolutions it supports",
+                    config.device, config.width, config.height, width, height
+                )));
+            }
+
+            Ok(CameraSource {
+                cap,
+                device: config.device.clone(),
+                frames: config.frames,
+                stale_frames: config.stale_frames,
+                combination: config.frame_combination,
+            })
+        }
+
+        fn read_frame(&mut self) -> AppResult<Mat> {
             let mut mat = Mat::default();
 
-            if !self.cap.read(&mut mat).unwrap() {
-                panic!("Could not capture image!");
+            if !self.cap.read(&mut mat)? || mat.empty() {
+                return Err(AppError::Camera("Could not capture image!".to_string()));
             }
 
-            if half_screen {
-                mat = self.get_cropped_image(mat)?;
+            Ok(mat)
+        }
+    }
+
+    /// Merges frames of the same size and type pixel by pixel.
+    fn combine_frames(frames: &[Mat], combination: FrameCombination) -> AppResult<Mat> {
+        let first = &frames[0];
+        if frames.len() == 1 {
+            return Ok(first.try_clone()?);
+        }
+
+        // A camera that renegotiates its format mid-capture cannot be merged
+        for frame in &frames[1..] {
+            if frame.size()? != first.size()? || frame.typ() != first.typ() {
+                return Err(AppError::Camera(format!(
+                    "The camera switched from {}x{} (type {}) to {}x{} (type {}) between frames",
+                    first.cols(),
+                    first.rows(),
+                    first.typ(),
+                    frame.cols(),
+                    frame.rows(),
+                    frame.typ()
+                )));
             }
+        }
 
-            if color_correction {
-                mat = self.get_color_corrected_image(mat)?;
+        match combination {
+            FrameCombination::Mean => {
+                let sum_type = opencv::core::CV_MAKETYPE(opencv::core::CV_32F, first.channels());
+                let mut sum = Mat::zeros(first.rows(), first.cols(), sum_type)?.to_mat()?;
+                for frame in frames {
+                    opencv::imgproc::accumulate(frame, &mut sum, &opencv::core::no_array())?;
+                }
+
+                let mut mean = Mat::default();
+                sum.convert_to(&mut mean, first.typ(), 1.0 / frames.len() as f64, 0.0)?;
+                Ok(mean)
+            }
+            FrameCombination::Median => {
+                // Sorts every pixel across the frames with an odd-even transposition network,
+                // each comparison an element-wise min and max over whole frames
+                let mut sorted = frames
+                    .iter()
+                    .map(Mat::try_clone)
+                    .collect::<Result<Vec<_>, _>>()?;
+                for pass in 0..sorted.len() {
+                    for index in (pass % 2..sorted.len() - 1).step_by(2) {
+                        let mut lower = Mat::default();
+                        let mut upper = Mat::default();
+                        opencv::core::min(&sorted[index], &sorted[index + 1], &mut lower)?;
+                        opencv::core::max(&sorted[index], &sorted[index + 1], &mut upper)?;
+                        sorted[index] = lower;
+                        sorted[index + 1] = upper;
+                    }
+                }
+
+                let middle = sorted.len() / 2;
+                Ok(sorted.swap_remove(middle))
             }
+        }
+    }
 
-            opencv::imgcodecs::imwrite_def("output_image.jpg", &mat).unwrap();
+    /// Asks for a resolution and returns the one the camera actually switched to.
+    fn set_resolution(cap: &mut VideoCapture, width: i32, height: i32) -> AppResult<(i32, i32)> {
+        cap.set(opencv::videoio::CAP_PROP_FRAME_WIDTH, width as f64)?;
+        cap.set(opencv::videoio::CAP_PROP_FRAME_HEIGHT, height as f64)?;
 
-            let mut file = File::open("output_image.jpg")?;
-            let mut bytes_vector = Vec::new();
-            file.read_to_end(&mut bytes_vector)?;
-            Ok(bytes_vector)
+        Ok((
+            cap.get(opencv::videoio::CAP_PROP_FRAME_WIDTH)? as i32,
+            cap.get(opencv::videoio::CAP_PROP_FRAME_HEIGHT)? as i32,
+        ))
+    }
+
+    /// A camera found by `list_cameras` and the resolutions it accepted.
+    #[derive(Serialize)]
+    pub struct CameraInfo {
+        pub device: String,
+        pub backend: String,
+        pub resolutions: Vec<String>,
+    }
+
+    /// Probes the cameras OpenCV can open. On Linux the `/dev/video*` nodes are tried, on
+    /// other systems the first few device indices.
+    pub fn list_cameras(backend: CameraBackend) -> AppResult<Vec<CameraInfo>> {
+        let devices = if cfg!(target_os = "linux") {
+            let mut paths = std::fs::read_dir("/dev")?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path().display().to_string())
+                .filter(|path| path.starts_with("/dev/video"))
+                .collect::<Vec<_>>();
+            paths.sort_by_key(|path| path["/dev/video".len()..].parse::<u32>().unwrap_or(u32::MAX));
+            paths.into_iter().map(CameraDevice::Path).collect::<Vec<_>>()
+        } else {
+            (0..MAX_PROBED_DEVICES).map(CameraDevice::Index).collect()
+        };
+
+        let mut cameras = Vec::new();
+        for device in devices {
+            // A node that is busy or not a capture device should not hide the others
+            match probe_camera(&device, backend) {
+                Ok(Some(camera)) => cameras.push(camera),
+                Ok(None) => {}
+                Err(e) => eprintln!("Skipping {}: {}", device, e),
+            }
+        }
+
+        Ok(cameras)
+    }
+
+    /// The resolutions `device` accepts, `None` when it cannot be opened.
+    fn probe_camera(device: &CameraDevice, backend: CameraBackend) -> AppResult<Option<CameraInfo>> {
+        let mut cap = device.open(backend)?;
+        if !cap.is_opened()? {
+            return Ok(None);
+        }
+
+        let mut resolutions = Vec::new();
+        for (width, height) in PROBED_RESOLUTIONS {
+            let resolution = set_resolution(&mut cap, width, height)?;
+            if resolution == (width, height) {
+                resolutions.push(format!("{}x{}", width, height));
+            }
+        }
+
+        Ok(Some(CameraInfo {
+            device: match device {
+                CameraDevice::Index(index) => index.to_string(),
+                CameraDevice::Path(path) => path.clone(),
+            },
+            backend: cap.get_backend_name()?,
+            resolutions,
+        }))
+    }
+
+    impl FrameSource for CameraSource {
+        fn next_frame(&mut self) -> AppResult<Option<Mat>> {
+            // The driver buffers a few frames, which may be from before the enter press
+            for _ in 0..self.stale_frames {
+                self.cap.grab()?;
+            }
+
+            let frames = (0..self.frames.max(1))
+                .map(|_| self.read_frame())
+                .collect::<AppResult<Vec<_>>>()?;
+
+            combine_frames(&frames, self.combination).map(Some)
         }
 
-        fn load_image_from_file(&mut self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-            let image_mat = opencv::imgcodecs::imread_def("test_image.jpg")?;
-            let image_mat = self.get_color_corrected_image(image_mat)?;
+        fn describe(&self) -> String {
+            self.device.to_string()
+        }
+    }
+
+    impl FrameProcessor {
+        pub fn new(config: &Config) -> Self {
+            let camera = &config.camera;
+
+            Self {
+                threshold: camera.threshold,
+                encoding: FrameEncoding {
+                    format: camera.image_format,
+                    quality: camera.image_quality,
+                    debug_frame_path: camera.debug_frame_path.clone(),
+                },
+                detection: config.dialogue_detection.clone(),
+                perspective: config.perspective.clone(),
+                steps: config.preprocessing.steps.clone(),
+            }
+        }
+
+        /// The preprocessing chain applied when color correction is on.
+        pub fn steps(&self) -> &[PreprocessStep] {
+            &self.steps
+        }
+
+        /// Turns the step at `index` of the preprocessing chain on or off.
+        pub fn toggle_step(&mut self, index: usize) -> AppResult<&PreprocessStep> {
+            let count = self.steps.len();
+            let step = self.steps.get_mut(index).ok_or_else(|| {
+                AppError::Config(format!("No preprocessing step {}, there are {}", index, count))
+            })?;
+            step.enabled = !step.enabled;
 
-            opencv::imgcodecs::imwrite_def("output_image.jpg", &image_mat).unwrap();
+            Ok(step)
+        }
+
+        /// Crops the frame to its text like `crop`, then applies the enabled corrections
+        /// and encodes it for OCR.
+        pub fn process(
+            &self,
+            mat: Mat,
+            region: Option<&Region>,
+            usage_options: &UsageOptions,
+        ) -> AppResult<Vec<u8>> {
+            let mat = self.crop(mat, region, usage_options)?;
+            let mat = self.correct(mat, usage_options)?;
+            self.encode(&mat)
+        }
+
+        /// Straightens the screen and crops the frame to the detected dialogue box, or to
+        /// `region` when detection is off or finds nothing.
+        pub fn crop(
+            &self,
+            mat: Mat,
+            region: Option<&Region>,
+            usage_options: &UsageOptions,
+        ) -> AppResult<Mat> {
+            let mut mat = self.rectify_screen(mat, usage_options)?;
+
+            let detected = if usage_options.detect_dialogue {
+                let detected = detect_dialogue_box(&mat, &self.detection)?;
+                if usage_options.debug_printing {
+                    match detected {
+                        Some(rect) => println!(
+                            "Dialogue box found at x={} y={} {}x{}",
+                            rect.x, rect.y, rect.width, rect.height
+                        ),
+                        None => println!("No dialogue box found, using the configured region"),
+                    }
+                }
+                detected
+            } else {
+                None
+            };
+
+            let crop_rect = match (detected, region) {
+                (Some(rect), _) => Some(rect),
+                (None, Some(region)) => Some(region.to_rect(mat.cols(), mat.rows()).ok_or_else(|| {
+                    AppError::Config(format!(
+                        "Region {:?} lies outside the {}x{} frame",
+                        region,
+                        mat.cols(),
+                        mat.rows()
+                    ))
+                })?),
+                (None, None) => None,
+            };
+            if let Some(crop_rect) = crop_rect {
+                mat = self.get_cropped_image(mat, crop_rect)?;
+            }
+
+            Ok(mat)
+        }
+
+        /// Applies the enabled corrections to a cropped frame.
+        pub fn correct(&self, mat: Mat, usage_options: &UsageOptions) -> AppResult<Mat> {
+            if usage_options.color_correction {
+                return self.get_color_corrected_image(mat);
+            }
+
+            Ok(mat)
+        }
+
+        /// Reads a screenshot or photo and processes it as if the camera had captured it.
+        pub fn load_image_from_file(
+            &self,
+            path: &Path,
+            region: Option<&Region>,
+            usage_options: &UsageOptions,
+        ) -> AppResult<Vec<u8>> {
+            let image_mat = read_image(path)?;
+            let mat = self.crop(image_mat, region, usage_options)?;
+            let mat = self.correct(mat, usage_options)?;
+            self.encode_frame(&mat, Some(path))
+        }
+
+        /// Warps the screen to an upright rectangle, using the detected corners when
+        /// auto-detection finds them and the calibrated ones otherwise.
+        fn rectify_screen(&self, mat: Mat, usage_options: &UsageOptions) -> AppResult<Mat> {
+            let perspective = &self.perspective;
+            let detected = if perspective.auto_detect {
+                let detected = detect_screen(&mat, perspective)?;
+                if usage_options.debug_printing {
+                    match &detected {
+                        Some(corners) => println!("Screen found at {:?}", corners_to_config(corners)),
+                        None => println!("No screen found, using the calibrated corners"),
+                    }
+                }
+                detected
+            } else {
+                None
+            };
+
+            match detected.or_else(|| perspective.corners.as_ref().map(corners_from_config)) {
+                Some(corners) => rectify(&mat, &corners),
+                None => Ok(mat),
+            }
+        }
+
+        pub fn encode(&self, mat: &Mat) -> AppResult<Vec<u8>> {
+            self.encode_frame(mat, None)
+        }
+
+        /// Encodes the frame, which came from the file `image` if there was one.
+        fn encode_frame(&self, mat: &Mat, image: Option<&Path>) -> AppResult<Vec<u8>> {
+            let mut buffer = Vector::<u8>::new();
+            if !opencv::imgcodecs::imencode(
+                self.encoding.format.extension(),
+                mat,
+                &mut buffer,
+                &self.encoding.params(),
+            )? {
+                return Err(AppError::Camera(format!(
+                    "Could not encode frame as {:?}",
+                    self.encoding.format
+                )));
+            }
+
+            let bytes_vector = buffer.to_vec();
+            if let Some(path) = self.encoding.debug_frame_path(image) {
+                std::fs::write(path, &bytes_vector)?;
+            }
 
-            let mut file = File::open("output_image.jpg")?;
-            let mut bytes_vector = Vec::new();
-            file.read_to_end(&mut bytes_vector)?;
             Ok(bytes_vector)
         }
 
-        fn get_cropped_image(&mut self, mat: Mat) -> Result<Mat, Box<dyn std::error::Error>> {
-            let crop_rect = Rect::new(0, self.height / 2, self.width, self.height / 2);
+        fn get_cropped_image(&self, mat: Mat, crop_rect: Rect) -> AppResult<Mat> {
             let cropped_mat = mat.roi(crop_rect)?;
             Ok(cropped_mat.clone_pointee())
         }
 
-        fn isolate_white_text(&mut self, gray: Mat) -> Result<Mat, Box<dyn std::error::Error>> {
-            // let mut blurred = Mat::default();
-            // opencv::imgproc::gaussian_blur(&gray, &mut blurred, opencv::core::Size::new(5, 5), 0.0, 0.0, opencv::core::BorderTypes::BORDER_CONSTANT as i32, opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT)?;
-            //
-            // // Apply threshold: pixels brighter than 200 become 255 (white), others become 0 (black)
-            // let mut thresh = Mat::default();
-            // opencv::imgproc::threshold(&blurred, &mut thresh, 200.0, 255.0, opencv::imgproc::THRESH_BINARY)?;
-            //
-            // // Optional: clean up noise with morphology
-            // let mut morph = Mat::default();
-            // let kernel = opencv::imgproc::get_structuring_element(
-            //     opencv::imgproc::MORPH_RECT,
-            //     opencv::core::Size::new(3, 3),
-            //     opencv::core::Point::new(-1, -1),
-            // )?;
-            // opencv::imgproc::morphology_ex(&thresh, &mut morph, opencv::imgproc::MORPH_OPEN, &kernel, opencv::core::Point::new(-1, -1), 1, opencv::core::BORDER_CONSTANT, opencv::core::Scalar::default())?;
-
-            // let result = self.blur(gray, 5, 5, 0.0, 0.0)?;
-            let result = self.threshold(gray, threshold())?;
-            // let result = self.morph(result, 3, 3)?;
-
-            Ok(result)
-        }
-
-        fn blur(
-            &mut self,
-            source: Mat,
-            k_width: i32,
-            k_height: i32,
-            sigma_x: f64,
-            sigma_y: f64,
-        ) -> Result<Mat, Box<dyn std::error::Error>> {
-            let mut blurred = Mat::default();
-            opencv::imgproc::gaussian_blur(
-                &source,
-                &mut blurred,
-                opencv::core::Size::new(k_width, k_height),
-                sigma_x,
-                sigma_y,
-                opencv::core::BorderTypes::BORDER_CONSTANT as i32,
-                opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT,
-            )?;
-            Ok(blurred)
-        }
-
-        fn threshold(
-            &mut self,
-            source: Mat,
-            threshold: f64,
-        ) -> Result<Mat, Box<dyn std::error::Error>> {
-            // Apply threshold: pixels brighter than 200 become 255 (white), others become 0 (black)
-            let mut thresh = Mat::default();
-            opencv::imgproc::threshold(
-                &source,
-                &mut thresh,
-                threshold,
-                255.0,
-                opencv::imgproc::THRESH_BINARY,
-            )?;
-            Ok(thresh)
-        }
-
-        fn morph(
-            &mut self,
-            source: Mat,
-            width: i32,
-            height: i32,
-        ) -> Result<Mat, Box<dyn std::error::Error>> {
-            // Optional: clean up noise with morphology
-            let mut morph = Mat::default();
-            let kernel = opencv::imgproc::get_structuring_element(
-                opencv::imgproc::MORPH_RECT,
-                opencv::core::Size::new(width, height),
-                opencv::core::Point::new(-1, -1),
-            )?;
-            opencv::imgproc::morphology_ex(
-                &source,
-                &mut morph,
-                opencv::imgproc::MORPH_OPEN,
-                &kernel,
-                opencv::core::Point::new(-1, -1),
-                1,
-                opencv::core::BORDER_CONSTANT,
-                opencv::core::Scalar::default(),
-            )?;
-            Ok(morph)
-        }
-
-        fn get_color_corrected_image(&mut self, mat: Mat) -> Result<Mat, Box<dyn std::error::Error>> {
-            let mut grayscale_image = Mat::zeros_size(mat.size()?, mat.typ())?.to_mat()?;
-
-            opencv::imgproc::cvt_color(
-                &mat,
-                &mut grayscale_image,
-                opencv::imgproc::COLOR_BGR2GRAY,
-                0,
-                opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT,
-            )?;
-
-            let converted_image = self.isolate_white_text(grayscale_image)?;
-
-            Ok(converted_image)
-        }
-
-        fn get_backend() -> i32 {
-            if cfg!(target_os = "windows") {
-                opencv::videoio::CAP_DSHOW
-            } else {
-                opencv::videoio::CAP_ANY
-            }
+        fn get_color_corrected_image(&self, mat: Mat) -> AppResult<Mat> {
+            apply_steps(&self.steps, mat, self.threshold)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn frame(rows: &[[u8; 3]]) -> Mat {
+            Mat::from_slice_2d(rows).unwrap()
+        }
+
+        fn frames() -> Vec<Mat> {
+            vec![
+                frame(&[[10, 200, 0], [255, 30, 90]]),
+                frame(&[[20, 0, 0], [0, 30, 60]]),
+                frame(&[[30, 100, 255], [255, 30, 30]]),
+            ]
+        }
+
+        #[test]
+        fn takes_the_median_of_every_pixel() {
+            // A pixel that flickers in a single frame keeps the value of the other two
+            let median = combine_frames(&frames(), FrameCombination::Median).unwrap();
+            assert_eq!(median.data_bytes().unwrap(), [20, 100, 0, 255, 30, 60]);
+        }
+
+        #[test]
+        fn takes_the_mean_of_every_pixel() {
+            let mean = combine_frames(&frames(), FrameCombination::Mean).unwrap();
+            assert_eq!(mean.typ(), opencv::core::CV_8UC1);
+            assert_eq!(mean.data_bytes().unwrap(), [20, 100, 85, 170, 30, 60]);
+        }
+
+        #[test]
+        fn refuses_frames_of_different_sizes() {
+            let mut frames = frames();
+            frames.push(frame(&[[0, 0, 0]]));
+            assert!(matches!(
+                combine_frames(&frames, FrameCombination::Median),
+                Err(AppError::Camera(_))
+            ));
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,152 @@
+pub mod change_detection {
+    use opencv::core::{Mat, Size};
+    use opencv::prelude::MatTraitConst;
+    use crate::config::config::WatchConfig;
+    use crate::error::error::AppResult;
+
+    // Frames are compared as small grayscale thumbnails, which also hides compression noise
+    const THUMBNAIL_SIZE: Size = Size { width: 64, height: 36 };
+
+    pub fn thumbnail(mat: &Mat) -> AppResult<Mat> {
+        resized_gray(mat, THUMBNAIL_SIZE)
+    }
+
+    pub fn mean_difference(a: &Mat, b: &Mat) -> AppResult<f64> {
+        let mut difference = Mat::default();
+        opencv::core::absdiff(a, b, &mut difference)?;
+        Ok(opencv::core::mean(&difference, &opencv::core::no_array())?[0])
+    }
+
+    fn resized_gray(mat: &Mat, size: Size) -> AppResult<Mat> {
+        let mut gray = Mat::default();
+        let source = if mat.channels() == 1 {
+            mat
+        } else {
+            opencv::imgproc::cvt_color(
+                mat,
+                &mut gray,
+                opencv::imgproc::COLOR_BGR2GRAY,
+                0,
+                opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT,
+            )?;
+            &gray
+        };
+
+        let mut small = Mat::default();
+        opencv::imgproc::resize(source, &mut small, size, 0.0, 0.0, opencv::imgproc::INTER_AREA)?;
+        Ok(small)
+    }
+
+    /// Decides when the watched part of the screen shows something new: it differs from
+    /// the last capture that was handled, and it has stopped changing for `stable_polls`
+    /// polls in a row, so text that is still scrolling is not sent off.
+    pub struct ChangeDetector {
+        change_threshold: f64,
+        settle_threshold: f64,
+        stable_polls: u32,
+        previous: Option<Mat>,
+        stable_for: u32,
+        last_handled: Option<Mat>,
+    }
+
+    impl ChangeDetector {
+        pub fn new(config: &WatchConfig) -> Self {
+            Self {
+                change_threshold: config.change_threshold,
+                settle_threshold: config.settle_threshold,
+                stable_polls: config.stable_polls,
+                previous: None,
+                stable_for: 0,
+                last_handled: None,
+            }
+        }
+
+        /// Looks at the next poll of the watched area and returns true while it shows a
+        /// settled change that has not been handled yet.
+        pub fn observe(&mut self, mat: &Mat) -> AppResult<bool> {
+            let current = thumbnail(mat)?;
+            let settled = match &self.previous {
+                Some(previous) => mean_difference(previous, &current)? <= self.settle_threshold,
+                None => false,
+            };
+            self.stable_for = if settled { self.stable_for + 1 } else { 0 };
+
+            let changed = self.stable_for >= self.stable_polls
+                && match &self.last_handled {
+                    Some(last_handled) => mean_difference(last_handled, &current)? > self.change_threshold,
+                    None => true,
+                };
+            self.previous = Some(current);
+
+            Ok(changed)
+        }
+
+        /// Marks the last observed poll as handled, once its capture went through. A failed
+        /// capture is tried again on the next poll.
+        pub fn handled(&mut self) {
+            self.last_handled = self.previous.clone();
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use opencv::core::Scalar;
+
+        fn frame(brightness: f64) -> Mat {
+            Mat::new_rows_cols_with_default(72, 128, opencv::core::CV_8UC3, Scalar::all(brightness)).unwrap()
+        }
+
+        /// Observes each frame in turn, calling `handled` after every reported one.
+        fn reported(detector: &mut ChangeDetector, frames: &[f64]) -> Vec<bool> {
+            frames
+                .iter()
+                .map(|&brightness| {
+                    let changed = detector.observe(&frame(brightness)).unwrap();
+                    if changed {
+                        detector.handled();
+                    }
+                    changed
+                })
+                .collect()
+        }
+
+        #[test]
+        fn reports_a_change_once_it_has_settled() {
+            // Two polls without change are needed after the first, with the default settings
+            let mut detector = ChangeDetector::new(&WatchConfig::default());
+            assert_eq!(
+                reported(&mut detector, &[20.0, 20.0, 20.0, 20.0, 20.0, 200.0, 200.0, 200.0, 200.0]),
+                [false, false, true, false, false, false, false, true, false]
+            );
+        }
+
+        #[test]
+        fn keeps_changes_that_are_still_moving() {
+            let mut detector = ChangeDetector::new(&WatchConfig::default());
+            assert_eq!(reported(&mut detector, &[20.0, 60.0, 100.0, 140.0, 180.0]), [false; 5]);
+        }
+
+        #[test]
+        fn ignores_noise_below_the_change_threshold() {
+            let mut detector = ChangeDetector::new(&WatchConfig::default());
+            assert_eq!(
+                reported(&mut detector, &[100.0, 100.0, 100.0, 101.0, 101.0, 101.0]),
+                [false, false, true, false, false, false]
+            );
+        }
+
+        #[test]
+        fn reports_again_until_handled() {
+            let mut detector = ChangeDetector::new(&WatchConfig::default());
+            let mut observe = |brightness| detector.observe(&frame(brightness)).unwrap();
+            assert!(!observe(20.0));
+            assert!(!observe(20.0));
+            assert!(observe(20.0));
+            assert!(observe(20.0));
+
+            detector.handled();
+            assert!(!detector.observe(&frame(20.0)).unwrap());
+        }
+    }
+}
@@ -0,0 +1,184 @@
+pub mod cli {
+    use std::path::PathBuf;
+    use clap::{Parser, Subcommand, ValueEnum};
+    use crate::config::config::Overrides;
+    use crate::error::error::AppResult;
+    use crate::frame_source::frame_source::SourceSpec;
+    use crate::providers::providers::Provider;
+    use crate::utils::utils::{DialogueLine, TextToSpeechLanguage, TranslationResponse};
+    use crate::video_source::video_source::Sampling;
+
+    /// Reads Japanese text from a camera or image, translates it and reads it aloud.
+    #[derive(Parser)]
+    #[command(version, about)]
+    pub struct Cli {
+        /// Provider for every stage, overriding config.toml (google or azure)
+        #[arg(long, global = true)]
+        pub provider: Option<Provider>,
+
+        /// Language to translate to (en, fi or sv)
+        #[arg(long = "to", value_name = "LANGUAGE", global = true)]
+        pub target: Option<TextToSpeechLanguage>,
+
+        /// Where frames come from: camera, test-pattern, - for an image on stdin, or the path
+        /// of an image, a directory of images or a video. Defaults to camera.test_file or the camera
+        #[arg(long, value_name = "SOURCE", global = true)]
+        pub input: Option<SourceSpec>,
+
+        /// Profile with the regions of interest of a game, read from regions.profiles_dir
+        #[arg(long, value_name = "NAME", global = true)]
+        pub profile: Option<String>,
+
+        /// Named region of interest to crop frames to, overriding the profile's active region
+        #[arg(long, value_name = "NAME", global = true)]
+        pub region: Option<String>,
+
+        /// How recognized and translated text is printed
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text, global = true)]
+        pub format: OutputFormat,
+
+        /// Defaults to `run`
+        #[command(subcommand)]
+        pub command: Option<Command>,
+    }
+
+    #[derive(Subcommand)]
+    pub enum Command {
+        /// Capture from the camera whenever enter is pressed (the interactive loop)
+        Run,
+        /// Capture a single frame from the input, then recognize, translate and speak it
+        Capture {
+            /// Print the results without reading them aloud
+            #[arg(long)]
+            mute: bool,
+        },
+        /// Capture by itself whenever new text appears and has stopped changing
+        Watch {
+            /// Print the results without reading them aloud
+            #[arg(long)]
+            mute: bool,
+        },
+        /// List the cameras that can be opened and the resolutions they accept
+        ListCameras,
+        /// Capture a frame from the input and find the corners of the screen in it, for
+        /// perspective.corners in config.toml
+        Calibrate {
+            /// Also save the frame, to read the corners from by hand when none are found
+            #[arg(long, value_name = "FILE")]
+            save: Option<PathBuf>,
+        },
+        /// Recognize the text in an image file
+        Ocr {
+            image: PathBuf,
+        },
+        /// Translate Japanese text, read from stdin when not given
+        Translate {
+            text: Option<String>,
+        },
+        /// Recognize and translate image files, or every image in a directory
+        Batch {
+            #[arg(required = true)]
+            inputs: Vec<PathBuf>,
+
+            /// How many images are processed at the same time
+            #[arg(long, default_value_t = 4)]
+            jobs: usize,
+
+            /// Write the results to this file, as CSV when it ends in .csv and JSON otherwise
+            #[arg(long, value_name = "FILE")]
+            report: Option<PathBuf>,
+
+            /// Send the files as they are, without cropping or color correction
+            #[arg(long)]
+            raw: bool,
+        },
+        /// Transcribe the text shown in a recorded video file
+        Video {
+            file: PathBuf,
+
+            /// Sample a frame every this many seconds
+            #[arg(long, value_name = "SECONDS", conflicts_with = "scene_change")]
+            interval: Option<f64>,
+
+            /// Sample a frame whenever the picture changes, e.g. when a new line of dialogue appears
+            #[arg(long)]
+            scene_change: bool,
+
+            /// Also write the transcript to this file, as CSV when it ends in .csv and JSON otherwise
+            #[arg(long, value_name = "FILE")]
+            report: Option<PathBuf>,
+        },
+        /// Read text aloud, read from stdin when not given
+        Speak {
+            text: Option<String>,
+
+            /// Language of the text (ja, en, fi or sv)
+            #[arg(long, default_value = "ja")]
+            language: TextToSpeechLanguage,
+
+            /// Write the audio to this file instead of playing it
+            #[arg(long, value_name = "FILE")]
+            save: Option<PathBuf>,
+        },
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+    pub enum OutputFormat {
+        Text,
+        Json,
+    }
+
+    impl Cli {
+        pub fn overrides(&self) -> Overrides {
+            let (video_sampling, video_interval) = match &self.command {
+                Some(Command::Video { scene_change: true, .. }) => (Some(Sampling::SceneChange), None),
+                Some(Command::Video { interval: Some(interval), .. }) => (Some(Sampling::Interval), Some(*interval)),
+                _ => (None, None),
+            };
+
+            Overrides {
+                provider: self.provider,
+                translate_to: self.target,
+                video_sampling,
+                video_interval,
+                profile: self.profile.clone(),
+            }
+        }
+    }
+
+    pub fn print_translations(translated_text: &TranslationResponse) {
+        for translation in [
+            &translated_text.en_translation,
+            &translated_text.fi_translation,
+            &translated_text.sv_translation,
+        ] {
+            if !translation.is_empty() {
+                println!("{}\n", translation);
+            }
+        }
+    }
+
+    /// Prints the recognized text and its translations, as one JSON object per capture when
+    /// the output is meant for other programs.
+    pub fn print_results(
+        format: OutputFormat,
+        line: &DialogueLine,
+        translated_text: Option<&TranslationResponse>,
+    ) -> AppResult<()> {
+        match format {
+            OutputFormat::Text => match translated_text {
+                Some(translated_text) => print_translations(translated_text),
+                None => println!("{}", line),
+            },
+            OutputFormat::Json => {
+                let mut result = serde_json::to_value(line)?;
+                if let Some(translated_text) = translated_text {
+                    result["translations"] = serde_json::to_value(translated_text)?;
+                }
+                println!("{}", result);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,884 @@
+pub mod config {
+    use std::collections::{BTreeMap, HashMap};
+    use std::path::Path;
+    use std::str::FromStr;
+    use serde::Deserialize;
+    use crate::camera_capture::camera_capture::{CameraBackend, CameraDevice, FrameCombination, ImageFormat};
+    use crate::error::error::{AppError, AppResult};
+    use crate::perspective::perspective::{corners_from_config, is_convex};
+    use crate::preprocessing::preprocessing::{default_steps, PreprocessStep};
+    use crate::providers::providers::Provider;
+    use crate::regions::regions::{Profile, Region, RegionUnit};
+    use crate::utils::utils::{TextToSpeechLanguage, UsageOptions};
+    use crate::video_source::video_source::Sampling;
+
+    const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+    /// The longest `video.interval` and `watch.poll_interval` accepted, a day in seconds.
+    const MAX_INTERVAL: f64 = 86_400.0;
+
+    /// All settings, read from `config.toml` (or the file named by `AUTO_TRANSLATOR_CONFIG`)
+    /// and then overridden by environment variables, which may come from `.env`.
+    #[derive(Deserialize, Default)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct Config {
+        pub providers: ProvidersConfig,
+        pub google: GoogleConfig,
+        pub azure: AzureConfig,
+        pub camera: CameraConfig,
+        pub languages: LanguagesConfig,
+        pub voices: VoicesConfig,
+        pub defaults: DefaultsConfig,
+        pub video: VideoConfig,
+        pub regions: RegionsConfig,
+        pub dialogue_detection: DialogueDetectionConfig,
+        pub speaker_detection: SpeakerDetectionConfig,
+        pub perspective: PerspectiveConfig,
+        pub preprocessing: PreprocessingConfig,
+        pub watch: WatchConfig,
+        pub dedup: DedupConfig,
+        pub cache: CacheConfig,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct ProvidersConfig {
+        pub ocr: Provider,
+        pub translation: Provider,
+        pub tts: Provider,
+    }
+
+    #[derive(Deserialize, Default)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct GoogleConfig {
+        pub project: Option<String>,
+        pub access_token: Option<String>,
+        pub credentials_file: Option<String>,
+        pub token_uri: Option<String>,
+    }
+
+    #[derive(Deserialize, Default)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct AzureConfig {
+        pub region: Option<String>,
+        pub ocr_url: Option<String>,
+        pub ocr_key: Option<String>,
+        pub text_to_speech_url: Option<String>,
+        pub text_to_speech_key: Option<String>,
+        pub translator_url: Option<String>,
+        pub translator_key: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct CameraConfig {
+        pub device: CameraDevice,
+        pub backend: CameraBackend,
+        pub width: i32,
+        pub height: i32,
+        pub frames: u32,
+        pub stale_frames: u32,
+        pub frame_combination: FrameCombination,
+        pub threshold: f64,
+        pub image_format: ImageFormat,
+        pub image_quality: i32,
+        pub debug_frame_path: Option<String>,
+        pub test_file: Option<String>,
+        /// Replaced by `[regions]`, still read so that older config files load.
+        pub crop: Option<Region>,
+    }
+
+    /// The regions of interest frames can be cropped to. A profile, read from
+    /// `<profiles_dir>/<profile>.toml`, adds its own regions and picks the active one.
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct RegionsConfig {
+        pub active: String,
+        pub profile: Option<String>,
+        pub profiles_dir: String,
+        pub named: BTreeMap<String, Region>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct LanguagesConfig {
+        pub translate_to: TextToSpeechLanguage,
+    }
+
+    /// Voice names per language code, e.g. `ja = "ja-JP-NanamiNeural"`. Languages that are
+    /// left out use the provider's built-in default voice.
+    #[derive(Deserialize, Default)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct VoicesConfig {
+        pub google: HashMap<String, String>,
+        pub azure: HashMap<String, String>,
+    }
+
+    /// Finding the dialogue window in the frame instead of cropping to a fixed region.
+    /// Sizes and positions are fractions of the frame.
+    #[derive(Deserialize, Clone)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct DialogueDetectionConfig {
+        pub enabled: bool,
+        pub min_area: f64,
+        pub min_center_y: f64,
+        pub min_rectangularity: f64,
+        pub edge_threshold: f64,
+    }
+
+    /// Straightening the screen when the camera films it at an angle. `corners` are the
+    /// pixel positions of the screen's top-left, top-right, bottom-right and bottom-left
+    /// corners in the camera frame; `auto_detect` looks for them in every frame instead.
+    #[derive(Deserialize, Clone)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct PerspectiveConfig {
+        pub corners: Option<[[f32; 2]; 4]>,
+        pub auto_detect: bool,
+        pub min_area: f64,
+        pub edge_threshold: f64,
+    }
+
+    /// When the watch subcommand decides the screen shows new text. Both thresholds are
+    /// mean pixel differences (0-255) of frame thumbnails: `change_threshold` is how much
+    /// a settled frame must differ from the last handled capture, `settle_threshold` the
+    /// difference below which two polls count as unchanged.
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct WatchConfig {
+        pub poll_interval: f64,
+        pub change_threshold: f64,
+        pub settle_threshold: f64,
+        pub stable_polls: u32,
+    }
+
+    /// Replaying the last result instead of running the pipeline again when a capture
+    /// shows the same dialogue. The recognized text decides; OCR is only skipped when the
+    /// preprocessed frame differs from the last by at most `frame_threshold`, the mean
+    /// pixel difference (0-255) of their thumbnails.
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct DedupConfig {
+        pub enabled: bool,
+        pub frame_threshold: f64,
+    }
+
+    /// Telling the speaker's name apart from the dialogue. The first line is taken to be
+    /// the name when it is no longer than `max_name_length` characters and one of the
+    /// enabled heuristics holds: it is indented more than `indent` pixels (0 to not check)
+    /// to the right of every other line, it sits on a name plate of its own, the rest is
+    /// speech in 「」 or 『』 brackets, or its text has the colour `name_color` (RGB, within
+    /// `color_tolerance`). The colour survives only when color correction is off or keeps
+    /// the colours.
+    #[derive(Deserialize, Clone)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct SpeakerDetectionConfig {
+        pub enabled: bool,
+        pub max_name_length: usize,
+        pub indent: i32,
+        pub name_plate: bool,
+        pub brackets: bool,
+        pub name_color: Option<[u8; 3]>,
+        pub color_tolerance: f64,
+    }
+
+    /// Keeping translations and synthesized speech on disk, so text that has been seen
+    /// before costs nothing. The least recently used entries go once the directory grows
+    /// past `max_size_mb`.
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct CacheConfig {
+        pub enabled: bool,
+        pub directory: String,
+        pub max_size_mb: u64,
+    }
+
+    /// The image operations applied, in order, when color correction is on.
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct PreprocessingConfig {
+        pub steps: Vec<PreprocessStep>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct DefaultsConfig {
+        pub playback_en: bool,
+        pub playback_fi: bool,
+        pub use_translation: bool,
+        #[serde(alias = "half_screen")]
+        pub use_region: bool,
+        pub debug_printing: bool,
+        pub color_correction: bool,
+    }
+
+    /// How frames are picked from video files. `interval` is in seconds, the scene change
+    /// threshold is the mean difference between grayscale frames, from 0 to 255.
+    #[derive(Deserialize)]
+    #[serde(default, deny_unknown_fields)]
+    pub struct VideoConfig {
+        pub sampling: Sampling,
+        pub interval: f64,
+        pub scene_change_threshold: f64,
+    }
+
+    /// Settings given on the command line, which win over both the file and the environment.
+    #[derive(Default)]
+    pub struct Overrides {
+        pub provider: Option<Provider>,
+        pub translate_to: Option<TextToSpeechLanguage>,
+        pub video_sampling: Option<Sampling>,
+        pub video_interval: Option<f64>,
+        pub profile: Option<String>,
+    }
+
+    impl Default for ProvidersConfig {
+        fn default() -> Self {
+            Self {
+                ocr: Provider::Google,
+                translation: Provider::Google,
+                tts: Provider::Google,
+            }
+        }
+    }
+
+    impl Default for CameraConfig {
+        fn default() -> Self {
+            Self {
+                device: CameraDevice::Index(0),
+                backend: CameraBackend::Auto,
+                width: 3840,
+                height: 2160,
+                frames: 1,
+                stale_frames: 0,
+                frame_combination: FrameCombination::Mean,
+                threshold: 200.0,
+                image_format: ImageFormat::Jpeg,
+                image_quality: 95,
+                debug_frame_path: None,
+                test_file: None,
+                crop: None,
+            }
+        }
+    }
+
+    impl Default for LanguagesConfig {
+        fn default() -> Self {
+            Self {
+                translate_to: TextToSpeechLanguage::Swedish,
+            }
+        }
+    }
+
+    impl Default for VideoConfig {
+        fn default() -> Self {
+            Self {
+                sampling: Sampling::Interval,
+                interval: 2.0,
+                scene_change_threshold: 12.0,
+            }
+        }
+    }
+
+    impl Default for RegionsConfig {
+        fn default() -> Self {
+            let bottom_half = Region {
+                unit: RegionUnit::Fraction,
+                x: 0.0,
+                y: 0.5,
+                width: 1.0,
+                height: 0.5,
+            };
+
+            Self {
+                active: "dialogue".to_string(),
+                profile: None,
+                profiles_dir: "profiles".to_string(),
+                named: BTreeMap::from([("dialogue".to_string(), bottom_half)]),
+            }
+        }
+    }
+
+    impl Default for DialogueDetectionConfig {
+        fn default() -> Self {
+            Self {
+                enabled: false,
+                min_area: 0.1,
+                min_center_y: 0.5,
+                min_rectangularity: 0.8,
+                edge_threshold: 100.0,
+            }
+        }
+    }
+
+    impl Default for PerspectiveConfig {
+        fn default() -> Self {
+            Self {
+                corners: None,
+                auto_detect: false,
+                min_area: 0.3,
+                edge_threshold: 60.0,
+            }
+        }
+    }
+
+    impl Default for WatchConfig {
+        fn default() -> Self {
+            Self {
+                poll_interval: 0.5,
+                change_threshold: 2.0,
+                settle_threshold: 3.0,
+                stable_polls: 2,
+            }
+        }
+    }
+
+    impl Default for DedupConfig {
+        fn default() -> Self {
+            Self {
+                enabled: true,
+                frame_threshold: 0.5,
+            }
+        }
+    }
+
+    impl Default for SpeakerDetectionConfig {
+        fn default() -> Self {
+            Self {
+                enabled: true,
+                max_name_length: 12,
+                indent: 60,
+                name_plate: false,
+                brackets: true,
+                name_color: None,
+                color_tolerance: 40.0,
+            }
+        }
+    }
+
+    impl Default for CacheConfig {
+        fn default() -> Self {
+            Self {
+                enabled: true,
+                directory: "cache".to_string(),
+                max_size_mb: 200,
+            }
+        }
+    }
+
+    impl Default for PreprocessingConfig {
+        fn default() -> Self {
+            Self {
+                steps: default_steps(),
+            }
+        }
+    }
+
+    impl Default for DefaultsConfig {
+        fn default() -> Self {
+            Self {
+                playback_en: false,
+                playback_fi: false,
+                use_translation: true,
+                use_region: true,
+                debug_printing: false,
+                color_correction: false,
+            }
+        }
+    }
+
+    impl Config {
+        /// Loads and validates the configuration, reporting every problem at once.
+        pub fn load(overrides: &Overrides) -> AppResult<Self> {
+            let (config, mut problems) = Self::read(overrides)?;
+            config.validate(&mut problems);
+
+            if problems.is_empty() {
+                Ok(config)
+            } else {
+                Err(AppError::Config(format!(
+                    "{} problem(s) found:\n  - {}",
+                    problems.len(),
+                    problems.join("\n  - ")
+                )))
+            }
+        }
+
+        /// Loads the configuration without validating it, for the commands that only read
+        /// a setting or two. Environment variables that cannot be parsed are ignored.
+        pub fn load_unchecked(overrides: &Overrides) -> AppResult<Self> {
+            Ok(Self::read(overrides)?.0)
+        }
+
+        /// Reads the config file and applies the overrides, returning the problems found
+        /// in the environment variables.
+        fn read(overrides: &Overrides) -> AppResult<(Self, Vec<String>)> {
+            let path = dotenv::var("AUTO_TRANSLATOR_CONFIG")
+                .unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string());
+
+            let mut config = if Path::new(&path).exists() {
+                let contents = std::fs::read_to_string(&path)?;
+                toml::from_str(&contents)
+                    .map_err(|e| AppError::Config(format!("Invalid config file {}: {}", path, e)))?
+            } else {
+                Config::default()
+            };
+
+            config.migrate_deprecated();
+
+            let mut problems = Vec::new();
+            config.apply_env_overrides(&mut problems);
+            config.apply_overrides(overrides);
+
+            Ok((config, problems))
+        }
+
+        /// Moves settings from their old places to where they are read now.
+        fn migrate_deprecated(&mut self) {
+            if let Some(crop) = self.camera.crop.take() {
+                // An unchanged "dialogue" region is the default one, not the user's
+                let default_region = RegionsConfig::default().named.remove("dialogue");
+                if self.regions.named.get("dialogue") == default_region.as_ref() {
+                    self.regions.named.insert("dialogue".to_string(), crop);
+                    eprintln!("camera.crop is deprecated, move it to [regions.named.dialogue]");
+                } else {
+                    eprintln!("camera.crop is deprecated and ignored, [regions.named.dialogue] is used instead");
+                }
+            }
+        }
+
+        pub fn usage_options(&self) -> UsageOptions {
+            let defaults = &self.defaults;
+            let translate_to = self.languages.translate_to;
+
+            UsageOptions {
+                playback_en: defaults.playback_en,
+                playback_fi: defaults.playback_fi,
+                use_translation: defaults.use_translation,
+                translate_en: translate_to == TextToSpeechLanguage::English,
+                translate_fi: translate_to == TextToSpeechLanguage::Finnish,
+                translate_sv: translate_to == TextToSpeechLanguage::Swedish,
+                use_region: defaults.use_region,
+                detect_dialogue: self.dialogue_detection.enabled,
+                debug_printing: defaults.debug_printing,
+                color_correction: defaults.color_correction,
+            }
+        }
+
+        fn apply_env_overrides(&mut self, problems: &mut Vec<String>) {
+            env_parsed("OCR_PROVIDER", &mut self.providers.ocr, problems);
+            env_parsed("TRANSLATION_PROVIDER", &mut self.providers.translation, problems);
+            env_parsed("TTS_PROVIDER", &mut self.providers.tts, problems);
+
+            env_string("GOOGLE_CLOUD_PROJECT", &mut self.google.project);
+            env_string("GOOGLE_ACCESS_TOKEN", &mut self.google.access_token);
+            env_string("GOOGLE_APPLICATION_CREDENTIALS", &mut self.google.credentials_file);
+            env_string("GOOGLE_TOKEN_URI", &mut self.google.token_uri);
+
+            env_string("AZURE_REGION", &mut self.azure.region);
+            env_string("AZURE_OCR_URL", &mut self.azure.ocr_url);
+            env_string("AZURE_OCR_KEY", &mut self.azure.ocr_key);
+            env_string("AZURE_TEXT_TO_SPEECH_URL", &mut self.azure.text_to_speech_url);
+            env_string("AZURE_TEXT_TO_SPEECH_KEY", &mut self.azure.text_to_speech_key);
+            env_string("AZURE_TRANSLATOR_URL", &mut self.azure.translator_url);
+            env_string("AZURE_TRANSLATOR_KEY", &mut self.azure.translator_key);
+
+            env_parsed("CAMERA_DEVICE", &mut self.camera.device, problems);
+            env_parsed("CAMERA_BACKEND", &mut self.camera.backend, problems);
+            env_parsed("CAMERA_WIDTH", &mut self.camera.width, problems);
+            env_parsed("CAMERA_HEIGHT", &mut self.camera.height, problems);
+            env_parsed("CAMERA_FRAMES", &mut self.camera.frames, problems);
+            env_parsed("CAMERA_STALE_FRAMES", &mut self.camera.stale_frames, problems);
+            env_parsed("FRAME_COMBINATION", &mut self.camera.frame_combination, problems);
+            env_parsed("THRESHOLD", &mut self.camera.threshold, problems);
+            env_parsed("IMAGE_FORMAT", &mut self.camera.image_format, problems);
+            env_parsed("IMAGE_QUALITY", &mut self.camera.image_quality, problems);
+            env_string("SAVE_DEBUG_FRAME", &mut self.camera.debug_frame_path);
+
+            let mut use_test_file = self.camera.test_file.is_some();
+            env_parsed("USE_TEST_FILE", &mut use_test_file, problems);
+            self.camera.test_file = match (use_test_file, self.camera.test_file.take()) {
+                (true, test_file) => test_file.or_else(|| Some("test_image.jpg".to_string())),
+                (false, _) => None,
+            };
+
+            env_parsed("TRANSLATE_TO", &mut self.languages.translate_to, problems);
+
+            env_string("AUTO_TRANSLATOR_PROFILE", &mut self.regions.profile);
+            env_parsed("DETECT_DIALOGUE", &mut self.dialogue_detection.enabled, problems);
+            env_parsed("DETECT_SPEAKER", &mut self.speaker_detection.enabled, problems);
+
+            env_parsed("CACHE", &mut self.cache.enabled, problems);
+            let mut cache_directory = None;
+            env_string("CACHE_DIR", &mut cache_directory);
+            if let Some(directory) = cache_directory {
+                self.cache.directory = directory;
+            }
+
+            env_parsed("VIDEO_SAMPLING", &mut self.video.sampling, problems);
+            env_parsed("VIDEO_INTERVAL", &mut self.video.interval, problems);
+            env_parsed("VIDEO_SCENE_CHANGE_THRESHOLD", &mut self.video.scene_change_threshold, problems);
+        }
+
+        fn apply_overrides(&mut self, overrides: &Overrides) {
+            if let Some(provider) = overrides.provider {
+                self.providers = ProvidersConfig {
+                    ocr: provider,
+                    translation: provider,
+                    tts: provider,
+                };
+            }
+            if let Some(translate_to) = overrides.translate_to {
+                self.languages.translate_to = translate_to;
+            }
+            if let Some(sampling) = overrides.video_sampling {
+                self.video.sampling = sampling;
+            }
+            if let Some(interval) = overrides.video_interval {
+                self.video.interval = interval;
+            }
+            if let Some(profile) = &overrides.profile {
+                self.regions.profile = Some(profile.clone());
+            }
+        }
+
+        /// The configured regions of interest, combined with the selected profile.
+        pub fn profile(&self) -> AppResult<Profile> {
+            self.load_profile(self.regions.profile.as_deref())
+        }
+
+        /// The configured regions of interest, combined with the profile `name` if given.
+        pub fn load_profile(&self, name: Option<&str>) -> AppResult<Profile> {
+            let mut profile = Profile {
+                active: self.regions.active.clone(),
+                named: self.regions.named.clone(),
+            };
+
+            if let Some(name) = name {
+                profile.merge(Profile::load(Path::new(&self.regions.profiles_dir), name)?);
+
+                let problems = profile.problems();
+                if !problems.is_empty() {
+                    return Err(AppError::Config(format!(
+                        "Invalid profile '{}': {}",
+                        name,
+                        problems.join(", ")
+                    )));
+                }
+            }
+
+            Ok(profile)
+        }
+
+        /// Whether any stage of the pipeline is served by `provider`.
+        pub fn uses(&self, provider: Provider) -> bool {
+            let providers = &self.providers;
+            [providers.ocr, providers.translation, providers.tts].contains(&provider)
+        }
+
+        fn validate(&self, problems: &mut Vec<String>) {
+            // Only the credentials of the providers that are actually in use are required
+            let azure = &self.azure;
+            let mut required_settings = Vec::new();
+            if self.providers.ocr == Provider::Azure {
+                required_settings.push(("azure.ocr_url", &azure.ocr_url));
+                required_settings.push(("azure.ocr_key", &azure.ocr_key));
+            }
+            if self.providers.translation == Provider::Azure {
+                required_settings.push(("azure.translator_url", &azure.translator_url));
+                required_settings.push(("azure.translator_key", &azure.translator_key));
+                required_settings.push(("azure.region", &azure.region));
+            }
+            if self.providers.tts == Provider::Azure {
+                required_settings.push(("azure.text_to_speech_url", &azure.text_to_speech_url));
+                required_settings.push(("azure.text_to_speech_key", &azure.text_to_speech_key));
+            }
+            for (name, value) in required_settings {
+                check_present(name, value, problems);
+            }
+
+            if let Some(path) = self.google.credentials_file.as_ref().filter(|_| self.uses(Provider::Google)) {
+                if !Path::new(path).exists() {
+                    problems.push(format!("google.credentials_file {} does not exist", path));
+                }
+            }
+
+            let camera = &self.camera;
+            if let CameraDevice::Index(index) = camera.device {
+                if index < 0 {
+                    problems.push(format!("camera.device {} must not be negative", index));
+                }
+            }
+            if camera.width <= 0 || camera.height <= 0 {
+                problems.push(format!(
+                    "camera resolution {}x{} must be positive",
+                    camera.width, camera.height
+                ));
+            }
+            if camera.frames == 0 {
+                problems.push("camera.frames must be at least 1".to_string());
+            }
+            if !(0.0..=255.0).contains(&camera.threshold) {
+                problems.push(format!("camera.threshold {} must be between 0 and 255", camera.threshold));
+            }
+            if !(0..=100).contains(&camera.image_quality) {
+                problems.push(format!(
+                    "camera.image_quality {} must be between 0 and 100",
+                    camera.image_quality
+                ));
+            }
+
+            if let Some(path) = &camera.test_file {
+                if !Path::new(path).exists() {
+                    problems.push(format!("camera.test_file {} does not exist", path));
+                }
+            }
+
+            let regions = &self.regions;
+            for (name, region) in &regions.named {
+                if let Some(problem) = region.problem() {
+                    problems.push(format!("regions.named.{} {}", name, problem));
+                }
+            }
+            // A profile may define the active region, it is checked when the profile is loaded
+            if regions.profile.is_none() && !regions.named.contains_key(&regions.active) {
+                problems.push(format!(
+                    "regions.active '{}' is not one of the regions in regions.named",
+                    regions.active
+                ));
+            }
+
+            let detection = &self.dialogue_detection;
+            for (name, value) in [
+                ("min_area", detection.min_area),
+                ("min_center_y", detection.min_center_y),
+                ("min_rectangularity", detection.min_rectangularity),
+            ] {
+                if !(0.0..=1.0).contains(&value) {
+                    problems.push(format!("dialogue_detection.{} {} must be between 0 and 1", name, value));
+                }
+            }
+            if !detection.edge_threshold.is_finite() || detection.edge_threshold <= 0.0 {
+                problems.push(format!(
+                    "dialogue_detection.edge_threshold {} must be positive",
+                    detection.edge_threshold
+                ));
+            }
+
+            let perspective = &self.perspective;
+            if let Some(corners) = &perspective.corners {
+                let (width, height) = (self.camera.width as f32, self.camera.height as f32);
+                if !corners.iter().all(|&[x, y]| (0.0..=width).contains(&x) && (0.0..=height).contains(&y)) {
+                    problems.push(format!(
+                        "perspective.corners {:?} must lie inside the {}x{} camera frame",
+                        corners, self.camera.width, self.camera.height
+                    ));
+                } else if !is_convex(&corners_from_config(corners)) {
+                    problems.push(format!(
+                        "perspective.corners {:?} must be given as top-left, top-right, bottom-right and bottom-left",
+                        corners
+                    ));
+                }
+            }
+            if !(0.0..=1.0).contains(&perspective.min_area) {
+                problems.push(format!("perspective.min_area {} must be between 0 and 1", perspective.min_area));
+            }
+            if !perspective.edge_threshold.is_finite() || perspective.edge_threshold <= 0.0 {
+                problems.push(format!(
+                    "perspective.edge_threshold {} must be positive",
+                    perspective.edge_threshold
+                ));
+            }
+
+            for (index, step) in self.preprocessing.steps.iter().enumerate() {
+                if let Some(problem) = step.problem() {
+                    problems.push(format!("preprocessing.steps[{}]: {}", index, problem));
+                }
+            }
+
+            let video = &self.video;
+            if !video.interval.is_finite() || video.interval <= 0.0 || video.interval > MAX_INTERVAL {
+                problems.push(format!(
+                    "video.interval {} must be a positive number of seconds, at most {}",
+                    video.interval, MAX_INTERVAL
+                ));
+            }
+            if !(0.0..=255.0).contains(&video.scene_change_threshold) {
+                problems.push(format!(
+                    "video.scene_change_threshold {} must be between 0 and 255",
+                    video.scene_change_threshold
+                ));
+            }
+
+            let watch = &self.watch;
+            if !watch.poll_interval.is_finite() || watch.poll_interval <= 0.0 || watch.poll_interval > MAX_INTERVAL {
+                problems.push(format!(
+                    "watch.poll_interval {} must be a positive number of seconds, at most {}",
+                    watch.poll_interval, MAX_INTERVAL
+                ));
+            }
+            if !(0.0..=255.0).contains(&watch.change_threshold) {
+                problems.push(format!(
+                    "watch.change_threshold {} must be between 0 and 255",
+                    watch.change_threshold
+                ));
+            }
+            if !(0.0..=255.0).contains(&watch.settle_threshold) {
+                problems.push(format!(
+                    "watch.settle_threshold {} must be between 0 and 255",
+                    watch.settle_threshold
+                ));
+            }
+
+            if !(0.0..=255.0).contains(&self.dedup.frame_threshold) {
+                problems.push(format!(
+                    "dedup.frame_threshold {} must be between 0 and 255",
+                    self.dedup.frame_threshold
+                ));
+            }
+
+            let speaker_detection = &self.speaker_detection;
+            if speaker_detection.max_name_length == 0 {
+                problems.push("speaker_detection.max_name_length must be at least 1".to_string());
+            }
+            if speaker_detection.indent < 0 {
+                problems.push(format!(
+                    "speaker_detection.indent {} must not be negative",
+                    speaker_detection.indent
+                ));
+            }
+            if !(0.0..=255.0).contains(&speaker_detection.color_tolerance) {
+                problems.push(format!(
+                    "speaker_detection.color_tolerance {} must be between 0 and 255",
+                    speaker_detection.color_tolerance
+                ));
+            }
+
+            if self.cache.enabled {
+                if self.cache.directory.trim().is_empty() {
+                    problems.push("cache.directory must not be empty".to_string());
+                }
+                if self.cache.max_size_mb == 0 {
+                    problems.push("cache.max_size_mb must be at least 1".to_string());
+                }
+            }
+
+            if self.languages.translate_to == TextToSpeechLanguage::Japanese {
+                problems.push("languages.translate_to must be en, fi or sv".to_string());
+            }
+        }
+    }
+
+    /// Unwraps a setting that validation already checked, for use by the clients.
+    pub fn required<'a>(name: &str, value: &'a Option<String>) -> AppResult<&'a str> {
+        value
+            .as_deref()
+            .filter(|value| !value.trim().is_empty())
+            .ok_or_else(|| AppError::Config(format!("{} is missing", name)))
+    }
+
+    fn check_present(name: &str, value: &Option<String>, problems: &mut Vec<String>) {
+        if let Err(AppError::Config(problem)) = required(name, value) {
+            problems.push(problem);
+        }
+    }
+
+    fn env_string(name: &str, target: &mut Option<String>) {
+        if let Ok(value) = dotenv::var(name) {
+            *target = Some(value).filter(|value| !value.trim().is_empty());
+        }
+    }
+
+    fn env_parsed<T>(name: &str, target: &mut T, problems: &mut Vec<String>)
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        if let Ok(value) = dotenv::var(name) {
+            match value.trim().parse() {
+                Ok(parsed) => *target = parsed,
+                Err(e) => problems.push(format!("{}='{}' is invalid: {}", name, value, e)),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn problems(contents: &str) -> Vec<String> {
+            let mut config: Config = toml::from_str(contents).unwrap();
+            config.migrate_deprecated();
+            let mut problems = Vec::new();
+            config.validate(&mut problems);
+            problems
+        }
+
+        #[test]
+        fn accepts_the_defaults() {
+            assert_eq!(problems(""), Vec::<String>::new());
+        }
+
+        #[test]
+        fn rejects_unknown_settings() {
+            assert!(toml::from_str::<Config>("[camera]\nwidht = 1920").is_err());
+            assert!(toml::from_str::<Config>("[cameras]\nwidth = 1920").is_err());
+        }
+
+        #[test]
+        fn requires_the_credentials_of_the_providers_in_use() {
+            let problems = problems("[providers]\ntranslation = \"azure\"");
+            assert_eq!(
+                problems,
+                ["azure.translator_url is missing", "azure.translator_key is missing", "azure.region is missing"]
+            );
+        }
+
+        #[test]
+        fn reports_every_problem_at_once() {
+            let problems = problems(
+                "[camera]\n\
+                 frames = 0\n\
+                 image_quality = 150\n\
+                 [watch]\n\
+                 poll_interval = 0.0\n\
+                 [cache]\n\
+                 enabled = true\n\
+                 max_size_mb = 0",
+            );
+            assert_eq!(problems.len(), 4, "{:?}", problems);
+            assert!(problems.iter().any(|problem| problem.starts_with("camera.frames")));
+            assert!(problems.iter().any(|problem| problem.starts_with("camera.image_quality 150")));
+            assert!(problems.iter().any(|problem| problem.starts_with("watch.poll_interval 0")));
+            assert!(problems.iter().any(|problem| problem.starts_with("cache.max_size_mb")));
+        }
+
+        #[test]
+        fn rejects_thresholds_and_intervals_out_of_range() {
+            let problems = problems(
+                "[dialogue_detection]\n\
+                 edge_threshold = nan\n\
+                 [perspective]\n\
+                 edge_threshold = -nan\n\
+                 [video]\n\
+                 interval = inf\n\
+                 [watch]\n\
+                 poll_interval = 1e300",
+            );
+            assert_eq!(problems.len(), 4, "{:?}", problems);
+            assert!(problems.iter().any(|problem| problem.starts_with("dialogue_detection.edge_threshold NaN")));
+            assert!(problems.iter().any(|problem| problem.starts_with("perspective.edge_threshold NaN")));
+            assert!(problems.iter().any(|problem| problem.starts_with("video.interval inf")));
+            assert!(problems.iter().any(|problem| problem.starts_with("watch.poll_interval 1")));
+        }
+
+        #[test]
+        fn keeps_the_screen_corners_inside_the_frame() {
+            let corners = |corners: &str| {
+                problems(&format!("[camera]\nwidth = 1920\nheight = 1080\n\n[perspective]\ncorners = {}", corners))
+            };
+            assert!(corners("[[10, 10], [1900, 20], [1910, 1070], [0, 1060]]").is_empty());
+            assert_eq!(corners("[[10, 10], [3500, 20], [3400, 2000], [0, 1060]]").len(), 1);
+            assert_eq!(corners("[[10, 10], [1910, 1070], [1900, 20], [0, 1060]]").len(), 1);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+pub mod dedup {
+    use opencv::core::Mat;
+    use crate::change_detection::change_detection::mean_difference;
+    use crate::config::config::DedupConfig;
+    use crate::error::error::AppResult;
+    use crate::utils::utils::{DialogueLine, SynthesizedAudio, TextToSpeechLanguage, TranslationResponse};
+
+    /// Everything a capture produced, kept so the same dialogue can be shown and read
+    /// aloud again without paying for OCR, translation or speech a second time.
+    pub struct CaptureResult {
+        pub line: DialogueLine,
+        pub languages: Vec<TextToSpeechLanguage>,
+        pub translations: TranslationResponse,
+        pub audio: Vec<(TextToSpeechLanguage, SynthesizedAudio)>,
+    }
+
+    /// Remembers the last capture, recognized by its normalized text or, before OCR, by
+    /// a thumbnail of its preprocessed frame that is nearly identical.
+    pub struct Deduplicator {
+        enabled: bool,
+        frame_threshold: f64,
+        last: Option<(Mat, String, CaptureResult)>,
+    }
+
+    impl Deduplicator {
+        pub fn new(config: &DedupConfig) -> Self {
+            Self {
+                enabled: config.enabled,
+                frame_threshold: config.frame_threshold,
+                last: None,
+            }
+        }
+
+        /// The last result when `thumbnail` shows practically the same frame, translated to
+        /// the same languages. Frames only count as the same when hardly a pixel differs, as
+        /// new text in the same dialogue window changes little of the thumbnail.
+        pub fn by_frame(
+            &self,
+            thumbnail: &Mat,
+            languages: &[TextToSpeechLanguage],
+        ) -> AppResult<Option<&CaptureResult>> {
+            let Some((last_thumbnail, _, result)) = self.last.as_ref() else {
+                return Ok(None);
+            };
+            if !self.enabled || result.languages != languages {
+                return Ok(None);
+            }
+
+            let same = mean_difference(thumbnail, last_thumbnail)? <= self.frame_threshold;
+            Ok(same.then_some(result))
+        }
+
+        /// The last result when `text` is the same dialogue, translated to the same languages.
+        /// The thumbnail is updated so the next identical frame is caught before OCR.
+        pub fn by_text(
+            &mut self,
+            thumbnail: &Mat,
+            text: &str,
+            languages: &[TextToSpeechLanguage],
+        ) -> Option<&CaptureResult> {
+            let normalized = normalize_text(text);
+            let enabled = self.enabled;
+
+            self.last
+                .as_mut()
+                .filter(|(_, last_text, result)| {
+                    enabled && !normalized.is_empty() && *last_text == normalized && result.languages == languages
+                })
+                .map(|(last_thumbnail, _, result)| {
+                    *last_thumbnail = thumbnail.clone();
+                    &*result
+                })
+        }
+
+        pub fn remember(&mut self, thumbnail: Mat, result: CaptureResult) {
+            if self.enabled {
+                self.last = Some((thumbnail, normalize_text(&result.line.to_string()), result));
+            }
+        }
+    }
+
+    /// The text without whitespace and punctuation, with full-width letters and digits
+    /// made half-width, so OCR noise like a stray space does not count as new dialogue.
+    pub fn normalize_text(text: &str) -> String {
+        text.chars()
+            .map(|c| match c {
+                // Full-width ASCII forms, e.g. "Ａ" or "１"
+                '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+                c => c,
+            })
+            .filter(|c| !c.is_whitespace() && !c.is_ascii_punctuation() && !is_cjk_punctuation(*c))
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+
+    fn is_cjk_punctuation(c: char) -> bool {
+        matches!(c, '\u{3000}'..='\u{303F}' | '\u{30FB}' | '\u{2026}' | '\u{2025}')
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::utils::utils::TextToSpeechLanguage::{English, Finnish};
+
+        fn deduplicator(enabled: bool) -> Deduplicator {
+            Deduplicator::new(&DedupConfig {
+                enabled,
+                ..DedupConfig::default()
+            })
+        }
+
+        fn result(text: &str, languages: &[TextToSpeechLanguage]) -> CaptureResult {
+            CaptureResult {
+                line: DialogueLine {
+                    text: text.to_string(),
+                    ..DialogueLine::default()
+                },
+                languages: languages.to_vec(),
+                translations: TranslationResponse::default(),
+                audio: Vec::new(),
+            }
+        }
+
+        #[test]
+        fn ignores_ocr_noise() {
+            assert_eq!(normalize_text("こんにちは、 世界！"), normalize_text("こんにちは世界"));
+            assert_eq!(normalize_text("ＨＰ　１２０"), "hp120");
+            assert_eq!(normalize_text("「……はい。」"), "はい");
+            assert_ne!(normalize_text("はい"), normalize_text("いいえ"));
+        }
+
+        #[test]
+        fn replays_the_same_text() {
+            let mut deduplicator = deduplicator(true);
+            deduplicator.remember(Mat::default(), result("お待たせしました。", &[English]));
+
+            assert!(deduplicator.by_text(&Mat::default(), "お待たせ しました", &[English]).is_some());
+            assert!(deduplicator.by_text(&Mat::default(), "行きましょう。", &[English]).is_none());
+        }
+
+        #[test]
+        fn translates_again_for_other_languages() {
+            let mut deduplicator = deduplicator(true);
+            deduplicator.remember(Mat::default(), result("お待たせしました。", &[English]));
+
+            assert!(deduplicator.by_text(&Mat::default(), "お待たせしました。", &[Finnish]).is_none());
+            assert!(deduplicator.by_frame(&Mat::default(), &[Finnish]).unwrap().is_none());
+        }
+
+        #[test]
+        fn never_replays_empty_text() {
+            let mut deduplicator = deduplicator(true);
+            deduplicator.remember(Mat::default(), result("", &[English]));
+
+            assert!(deduplicator.by_text(&Mat::default(), " 。", &[English]).is_none());
+        }
+
+        #[test]
+        fn remembers_nothing_when_disabled() {
+            let mut deduplicator = deduplicator(false);
+            deduplicator.remember(Mat::default(), result("お待たせしました。", &[English]));
+
+            assert!(deduplicator.by_frame(&Mat::default(), &[English]).unwrap().is_none());
+            assert!(deduplicator.by_text(&Mat::default(), "お待たせしました。", &[English]).is_none());
+        }
+    }
+}
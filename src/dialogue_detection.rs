@@ -0,0 +1,116 @@
+pub mod dialogue_detection {
+    use opencv::core::{Mat, Point, Rect, Size, Vector};
+    use opencv::prelude::MatTraitConst;
+    use crate::config::config::DialogueDetectionConfig;
+    use crate::error::error::AppResult;
+
+    // Polygon approximation tolerance, relative to the contour's perimeter
+    const APPROXIMATION_EPSILON: f64 = 0.02;
+    // Boxes covering nearly the whole frame are the screen border, not a dialogue window
+    const MAX_AREA: f64 = 0.95;
+
+    /// Looks for the dialogue window of a game: the largest rectangle with a high-contrast
+    /// border whose centre lies in the lower part of the frame. `None` when there is none.
+    pub fn detect_dialogue_box(mat: &Mat, config: &DialogueDetectionConfig) -> AppResult<Option<Rect>> {
+        let contours = find_edge_contours(mat, config.edge_threshold)?;
+
+        let (width, height) = (mat.cols() as f64, mat.rows() as f64);
+        let frame_area = width * height;
+        let mut best: Option<Rect> = None;
+
+        for contour in contours {
+            let rect = opencv::imgproc::bounding_rect(&contour)?;
+            let area = rect.area() as f64;
+            if area < config.min_area * frame_area || area > MAX_AREA * frame_area {
+                continue;
+            }
+
+            let center_y = rect.y as f64 + rect.height as f64 / 2.0;
+            if center_y < config.min_center_y * height {
+                continue;
+            }
+
+            // Rounded or decorated corners add a few vertices, anything more is not a box
+            let mut polygon = Vector::<Point>::new();
+            let perimeter = opencv::imgproc::arc_length(&contour, true)?;
+            opencv::imgproc::approx_poly_dp(&contour, &mut polygon, APPROXIMATION_EPSILON * perimeter, true)?;
+            if !(4..=8).contains(&polygon.len()) {
+                continue;
+            }
+
+            let rectangularity = opencv::imgproc::contour_area(&contour, false)? / area;
+            if rectangularity < config.min_rectangularity {
+                continue;
+            }
+
+            if !best.is_some_and(|best| best.area() >= rect.area()) {
+                best = Some(rect);
+            }
+        }
+
+        Ok(best)
+    }
+
+    /// The outlines of the regions enclosed by strong edges, such as window borders,
+    /// including the ones nested in others, as a dialogue box is inside the screen.
+    /// `edge_threshold` is the gradient strength an edge needs to be kept.
+    pub fn find_edge_contours(mat: &Mat, edge_threshold: f64) -> AppResult<Vector<Vector<Point>>> {
+        let mut gray = Mat::default();
+        opencv::imgproc::cvt_color(
+            mat,
+            &mut gray,
+            opencv::imgproc::COLOR_BGR2GRAY,
+            0,
+            opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+
+        let mut blurred = Mat::default();
+        opencv::imgproc::gaussian_blur(
+            &gray,
+            &mut blurred,
+            Size::new(5, 5),
+            0.0,
+            0.0,
+            opencv::core::BORDER_DEFAULT,
+            opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+
+        // Only strong edges survive, which keeps the background scenery out
+        let mut edges = Mat::default();
+        opencv::imgproc::canny(
+            &blurred,
+            &mut edges,
+            edge_threshold / 2.0,
+            edge_threshold,
+            3,
+            false,
+        )?;
+
+        // Closes small gaps in borders, e.g. where a name plate overlaps a dialogue box
+        let kernel = opencv::imgproc::get_structuring_element(
+            opencv::imgproc::MORPH_RECT,
+            Size::new(3, 3),
+            Point::new(-1, -1),
+        )?;
+        let mut closed = Mat::default();
+        opencv::imgproc::dilate(
+            &edges,
+            &mut closed,
+            &kernel,
+            Point::new(-1, -1),
+            2,
+            opencv::core::BORDER_CONSTANT,
+            opencv::imgproc::morphology_default_border_value()?,
+        )?;
+
+        let mut contours = Vector::<Vector<Point>>::new();
+        opencv::imgproc::find_contours_def(
+            &closed,
+            &mut contours,
+            opencv::imgproc::RETR_LIST,
+            opencv::imgproc::CHAIN_APPROX_SIMPLE,
+        )?;
+
+        Ok(contours)
+    }
+}
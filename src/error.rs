@@ -0,0 +1,223 @@
+pub mod error {
+    use std::fmt::{Display, Formatter};
+
+    /// Every failure the translator can run into, grouped by what the user can do about it.
+    #[derive(Debug)]
+    pub enum AppError {
+        Camera(String),
+        Network(reqwest::Error),
+        HttpStatus {
+            provider: &'static str,
+            status: u16,
+            code: Option<String>,
+            message: String,
+        },
+        Auth(String),
+        Quota(String),
+        Parse(String),
+        Audio(String),
+        Io(std::io::Error),
+        Config(String),
+    }
+
+    pub type AppResult<T> = Result<T, AppError>;
+
+    /// How many times a retryable request is repeated before its error is given up on.
+    pub const MAX_RETRIES: u64 = 2;
+
+    impl AppError {
+        /// Whether repeating the same request has a chance of succeeding.
+        pub fn is_retryable(&self) -> bool {
+            match self {
+                AppError::Network(_) | AppError::Quota(_) => true,
+                AppError::HttpStatus { status, .. } => *status == 429 || *status >= 500,
+                _ => false,
+            }
+        }
+
+        /// A short suggestion shown in the REPL next to the error.
+        pub fn hint(&self) -> Option<&'static str> {
+            match self {
+                AppError::Camera(_) => Some("Check that the camera is connected and not used by another program, 'list-cameras' shows the ones that can be opened."),
+                AppError::Network(_) => Some("Check your internet connection."),
+                AppError::Auth(_) | AppError::HttpStatus { status: 401 | 403, .. } => {
+                    Some("Check your API keys, or run 'gcloud auth login' for Google.")
+                }
+                AppError::Quota(_) | AppError::HttpStatus { status: 429, .. } => {
+                    Some("The provider is rate limiting requests, wait a moment before capturing again.")
+                }
+                AppError::Audio(_) => Some("Check that an audio output device is available."),
+                AppError::Config(_) => Some("Check config.toml and the settings in your .env file."),
+                _ => None,
+            }
+        }
+    }
+
+    /// Passes successful responses through and turns everything else into
+    /// `AppError::HttpStatus`, using the `{"error": {"code", "message"}}` payload that
+    /// both Google and Azure send back when there is one.
+    pub async fn check_status(
+        provider: &'static str,
+        response: reqwest::Response,
+    ) -> AppResult<reqwest::Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let (code, message) = match serde_json::from_str::<serde_json::Value>(&body) {
+            Ok(payload) => service_error(&payload),
+            Err(_) => (None, None),
+        };
+
+        Err(AppError::HttpStatus {
+            provider,
+            status: status.as_u16(),
+            code,
+            message: message
+                .or_else(|| Some(body.trim().to_string()).filter(|body| !body.is_empty()))
+                .or_else(|| status.canonical_reason().map(str::to_string))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Runs `request` again while it fails with a retryable error, waiting a second longer
+    /// before every attempt, up to `MAX_RETRIES` times.
+    pub async fn with_retries<T, F, Fut>(mut request: F) -> AppResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = AppResult<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match request().await {
+                Err(e) if e.is_retryable() && attempt < MAX_RETRIES => {
+                    attempt += 1;
+                    eprintln!("{}, retrying ({}/{})...", e, attempt, MAX_RETRIES);
+                    tokio::time::sleep(std::time::Duration::from_secs(attempt)).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Extracts the code and message from a provider error object. Google puts the
+    /// symbolic code in `status` next to the numeric `code`, Azure only sends `code`.
+    pub fn service_error(payload: &serde_json::Value) -> (Option<String>, Option<String>) {
+        let error = if payload["error"].is_object() {
+            &payload["error"]
+        } else {
+            payload
+        };
+
+        let code = match (&error["status"], &error["code"]) {
+            (serde_json::Value::String(status), _) => Some(status.clone()),
+            (_, serde_json::Value::String(code)) => Some(code.clone()),
+            (_, serde_json::Value::Number(code)) => Some(code.to_string()),
+            _ => None,
+        };
+        let message = error["message"].as_str().map(str::to_string);
+
+        (code, message)
+    }
+
+    impl Display for AppError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                AppError::Camera(message) => write!(f, "Camera error: {}", message),
+                AppError::Network(error) => write!(f, "Network error: {}", error),
+                AppError::HttpStatus { provider, status, code: Some(code), message } => {
+                    write!(f, "{} returned HTTP {} ({}): {}", provider, status, code, message)
+                }
+                AppError::HttpStatus { provider, status, code: None, message } => {
+                    write!(f, "{} returned HTTP {}: {}", provider, status, message)
+                }
+                AppError::Auth(message) => write!(f, "Authentication failed: {}", message),
+                AppError::Quota(message) => write!(f, "Quota exceeded: {}", message),
+                AppError::Parse(message) => write!(f, "Could not parse response: {}", message),
+                AppError::Audio(message) => write!(f, "Audio error: {}", message),
+                AppError::Io(error) => write!(f, "I/O error: {}", error),
+                AppError::Config(message) => write!(f, "Configuration error: {}", message),
+            }
+        }
+    }
+
+    impl std::error::Error for AppError {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            match self {
+                AppError::Network(error) => Some(error),
+                AppError::Io(error) => Some(error),
+                _ => None,
+            }
+        }
+    }
+
+    impl From<reqwest::Error> for AppError {
+        fn from(error: reqwest::Error) -> Self {
+            if error.is_decode() {
+                return AppError::Parse(error.to_string());
+            }
+
+            match error.status().map(|status| status.as_u16()) {
+                Some(401) | Some(403) => AppError::Auth(error.to_string()),
+                Some(429) => AppError::Quota(error.to_string()),
+                Some(status) => AppError::HttpStatus {
+                    provider: "HTTP",
+                    status,
+                    code: None,
+                    message: error.to_string(),
+                },
+                None => AppError::Network(error),
+            }
+        }
+    }
+
+    impl From<std::io::Error> for AppError {
+        fn from(error: std::io::Error) -> Self {
+            AppError::Io(error)
+        }
+    }
+
+    impl From<serde_json::Error> for AppError {
+        fn from(error: serde_json::Error) -> Self {
+            AppError::Parse(error.to_string())
+        }
+    }
+
+    impl From<base64::DecodeError> for AppError {
+        fn from(error: base64::DecodeError) -> Self {
+            AppError::Parse(error.to_string())
+        }
+    }
+
+    impl From<std::string::FromUtf8Error> for AppError {
+        fn from(error: std::string::FromUtf8Error) -> Self {
+            AppError::Parse(error.to_string())
+        }
+    }
+
+    impl From<reqwest::header::InvalidHeaderValue> for AppError {
+        fn from(error: reqwest::header::InvalidHeaderValue) -> Self {
+            AppError::Config(error.to_string())
+        }
+    }
+
+    impl From<csv::Error> for AppError {
+        fn from(error: csv::Error) -> Self {
+            AppError::Io(error.into())
+        }
+    }
+
+    impl From<opencv::Error> for AppError {
+        fn from(error: opencv::Error) -> Self {
+            AppError::Camera(error.to_string())
+        }
+    }
+
+    impl From<soloud::SoloudError> for AppError {
+        fn from(error: soloud::SoloudError) -> Self {
+            AppError::Audio(error.to_string())
+        }
+    }
+}
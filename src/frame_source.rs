@@ -0,0 +1,258 @@
+pub mod frame_source {
+    use std::collections::VecDeque;
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+    use opencv::core::{Mat, Point, Rect, Scalar, Vector};
+    use opencv::prelude::MatTraitConst;
+    use crate::camera_capture::camera_capture::CameraSource;
+    use crate::config::config::Config;
+    use crate::error::error::{AppError, AppResult};
+    use crate::video_source::video_source::VideoSource;
+
+    const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "webp", "bmp"];
+
+    /// Produces the BGR frames that are processed and sent to OCR, so the rest of the
+    /// pipeline does not care where the pixels come from.
+    pub trait FrameSource {
+        /// The next frame, or `None` once the source has run out.
+        fn next_frame(&mut self) -> AppResult<Option<Mat>>;
+
+        /// What the frames are read from, for messages.
+        fn describe(&self) -> String;
+    }
+
+    /// A frame source picked on the command line: `camera`, `test-pattern`, `-` for an
+    /// image piped on stdin, or the path of an image, a directory of images or a video.
+    #[derive(Debug, Clone)]
+    pub enum SourceSpec {
+        Camera,
+        TestPattern,
+        Stdin,
+        Path(PathBuf),
+    }
+
+    impl FromStr for SourceSpec {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim() {
+                "camera" => Ok(SourceSpec::Camera),
+                "test-pattern" => Ok(SourceSpec::TestPattern),
+                "-" => Ok(SourceSpec::Stdin),
+                path => Ok(SourceSpec::Path(PathBuf::from(path))),
+            }
+        }
+    }
+
+    /// Opens the requested source. Without one, `camera.test_file` is used when it is
+    /// set and the camera otherwise.
+    pub fn open_source(spec: Option<&SourceSpec>, config: &Config) -> AppResult<Box<dyn FrameSource>> {
+        let spec = match (spec, &config.camera.test_file) {
+            (Some(spec), _) => spec.clone(),
+            (None, Some(test_file)) => SourceSpec::Path(PathBuf::from(test_file)),
+            (None, None) => SourceSpec::Camera,
+        };
+
+        Ok(match spec {
+            SourceSpec::Camera => Box::new(CameraSource::new(&config.camera)?),
+            SourceSpec::TestPattern => {
+                Box::new(TestPatternSource::new(config.camera.width, config.camera.height))
+            }
+            SourceSpec::Stdin => Box::new(StdinSource { consumed: false }),
+            SourceSpec::Path(path) if path.is_dir() => Box::new(DirectorySource::new(&path)?),
+            SourceSpec::Path(path) if is_image(&path) => Box::new(ImageFileSource { path }),
+            SourceSpec::Path(path) => Box::new(VideoSource::open(&path, &config.video)?),
+        })
+    }
+
+    pub fn is_image(path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+    }
+
+    /// The images in a directory, sorted by name so they come in the order they were taken.
+    pub fn image_files(directory: &Path) -> AppResult<Vec<PathBuf>> {
+        let mut images = std::fs::read_dir(directory)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        images.retain(|path| path.is_file() && is_image(path));
+        images.sort();
+
+        Ok(images)
+    }
+
+    pub fn read_image(path: &Path) -> AppResult<Mat> {
+        decode_image(&std::fs::read(path)?, &path.display().to_string())
+    }
+
+    pub fn decode_image(bytes: &[u8], name: &str) -> AppResult<Mat> {
+        let mat = opencv::imgcodecs::imdecode(
+            &Vector::<u8>::from_slice(bytes),
+            opencv::imgcodecs::IMREAD_COLOR,
+        )?;
+
+        if mat.empty() {
+            return Err(AppError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} is not a supported image", name),
+            )));
+        }
+
+        Ok(mat)
+    }
+
+    /// A single image that is returned on every capture, like a camera pointed at a still.
+    pub struct ImageFileSource {
+        path: PathBuf,
+    }
+
+    impl FrameSource for ImageFileSource {
+        fn next_frame(&mut self) -> AppResult<Option<Mat>> {
+            read_image(&self.path).map(Some)
+        }
+
+        fn describe(&self) -> String {
+            self.path.display().to_string()
+        }
+    }
+
+    /// Every image in a directory, one per capture.
+    pub struct DirectorySource {
+        directory: PathBuf,
+        remaining: VecDeque<PathBuf>,
+    }
+
+    impl DirectorySource {
+        pub fn new(directory: &Path) -> AppResult<Self> {
+            Ok(Self {
+                directory: directory.to_path_buf(),
+                remaining: image_files(directory)?.into(),
+            })
+        }
+    }
+
+    impl FrameSource for DirectorySource {
+        fn next_frame(&mut self) -> AppResult<Option<Mat>> {
+            match self.remaining.pop_front() {
+                Some(path) => read_image(&path).map(Some),
+                None => Ok(None),
+            }
+        }
+
+        fn describe(&self) -> String {
+            format!("{} ({} images left)", self.directory.display(), self.remaining.len())
+        }
+    }
+
+    /// One encoded image piped on stdin, e.g. from a screenshot tool.
+    pub struct StdinSource {
+        consumed: bool,
+    }
+
+    impl FrameSource for StdinSource {
+        fn next_frame(&mut self) -> AppResult<Option<Mat>> {
+            if self.consumed {
+                return Ok(None);
+            }
+            self.consumed = true;
+
+            let mut bytes = Vec::new();
+            std::io::stdin().read_to_end(&mut bytes)?;
+            decode_image(&bytes, "stdin").map(Some)
+        }
+
+        fn describe(&self) -> String {
+            "stdin".to_string()
+        }
+    }
+
+    /// Generated frames with colour bars and a numbered dialogue box in the lower half,
+    /// for trying out the pipeline without a camera or screenshots.
+    pub struct TestPatternSource {
+        width: i32,
+        height: i32,
+        frame: u32,
+    }
+
+    impl TestPatternSource {
+        pub fn new(width: i32, height: i32) -> Self {
+            Self {
+                width,
+                height,
+                frame: 0,
+            }
+        }
+    }
+
+    impl FrameSource for TestPatternSource {
+        fn next_frame(&mut self) -> AppResult<Option<Mat>> {
+            self.frame += 1;
+            let (width, height) = (self.width, self.height);
+            let mut mat = Mat::new_rows_cols_with_default(
+                height,
+                width,
+                opencv::core::CV_8UC3,
+                Scalar::all(40.0),
+            )?;
+
+            // BGR colour bars across the upper half
+            let bars = [
+                (255.0, 255.0, 255.0),
+                (0.0, 255.0, 255.0),
+                (255.0, 255.0, 0.0),
+                (0.0, 255.0, 0.0),
+                (255.0, 0.0, 255.0),
+                (0.0, 0.0, 255.0),
+                (255.0, 0.0, 0.0),
+            ];
+            let bar_width = width / bars.len() as i32;
+            for (index, (blue, green, red)) in bars.into_iter().enumerate() {
+                opencv::imgproc::rectangle(
+                    &mut mat,
+                    Rect::new(index as i32 * bar_width, 0, bar_width, height / 2),
+                    Scalar::new(blue, green, red, 0.0),
+                    opencv::imgproc::FILLED,
+                    opencv::imgproc::LINE_8,
+                    0,
+                )?;
+            }
+
+            let dialogue_box = Rect::new(width / 20, height * 6 / 10, width * 9 / 10, height * 3 / 10);
+            opencv::imgproc::rectangle(
+                &mut mat,
+                dialogue_box,
+                Scalar::all(10.0),
+                opencv::imgproc::FILLED,
+                opencv::imgproc::LINE_8,
+                0,
+            )?;
+            opencv::imgproc::rectangle(
+                &mut mat,
+                dialogue_box,
+                Scalar::all(255.0),
+                (height / 200).max(1),
+                opencv::imgproc::LINE_8,
+                0,
+            )?;
+            opencv::imgproc::put_text(
+                &mut mat,
+                &format!("TEST PATTERN {}", self.frame),
+                Point::new(dialogue_box.x + width / 40, dialogue_box.y + height / 10),
+                opencv::imgproc::FONT_HERSHEY_SIMPLEX,
+                height as f64 / 400.0,
+                Scalar::all(255.0),
+                (height / 300).max(1),
+                opencv::imgproc::LINE_AA,
+                false,
+            )?;
+
+            Ok(Some(mat))
+        }
+
+        fn describe(&self) -> String {
+            format!("test pattern {}x{}", self.width, self.height)
+        }
+    }
+}
@@ -1,81 +1,83 @@
 pub mod google_client {
     use crate::{UsageOptions};
+    use async_trait::async_trait;
     use base64::prelude::*;
     use reqwest::header::{HeaderMap, HeaderValue};
     use reqwest::Client;
     use serde_json::json;
-    use std::fs::File;
-    use std::io::Write;
-    use std::process::Command;
-    use crate::utils::utils::{TextToSpeechLanguage, TranslationResponse};
-    use crate::utils::utils::TextToSpeechLanguage::{English, Finnish};
+    use std::collections::HashMap;
+    use crate::error::error::{check_status, service_error, AppError, AppResult};
+    use crate::google_auth::google_auth::TokenProvider;
+    use crate::providers::providers::{OcrEngine, SpeechSynthesizer, Translator};
+    use crate::utils::utils::{voice_locale, AudioCodec, InterpretedLine, SynthesizedAudio, TextToSpeechLanguage, TranslationResponse};
+    use crate::utils::utils::TextToSpeechLanguage::{English, Finnish, Japanese, Swedish};
+
+    const TTS_SAMPLE_RATE: u32 = 24000;
 
     pub struct GoogleCloudClient {
         client: Client,
         headers: HeaderMap,
-        token: String,
+        token_provider: TokenProvider,
+        voices: HashMap<String, String>,
     }
 
     impl GoogleCloudClient {
-        pub fn new() -> Self {
-            let token = if cfg!(target_os = "windows") {
-                String::from_utf8(
-                    Command::new("cmd")
-                        .args(["/C", "gcloud", "auth", "print-access-token"])
-                        .output()
-                        .unwrap()
-                        .stdout,
-                )
-                .unwrap()
-            } else {
-                String::from_utf8(
-                    Command::new("zsh")
-                        .args(["-c", "gcloud auth print-access-token"])
-                        .output()
-                        .unwrap()
-                        .stdout,
-                )
-                .unwrap()
-            };
-
-            let gcloud_config = if cfg!(target_os = "windows") {
-                String::from_utf8(
-                    Command::new("cmd")
-                        .args(["/C", "gcloud", "config", "list"])
-                        .output()
-                        .unwrap()
-                        .stdout,
-                )
-                .unwrap()
-            } else {
-                String::from_utf8(
-                    Command::new("zsh")
-                        .args(["-c", "gcloud config list"])
-                        .output()
-                        .unwrap()
-                        .stdout,
-                )
-                .unwrap()
-            };
-
-            let project = GoogleCloudClient::extract_google_project(gcloud_config.as_str())
-                .unwrap()
-                .trim();
-
+        pub fn new(
+            token_provider: TokenProvider,
+            project: &str,
+            voices: &HashMap<String, String>,
+        ) -> AppResult<Self> {
             let mut headers = HeaderMap::new();
             headers.insert(
                 "x-goog-user-project",
-                HeaderValue::from_str(project).unwrap(),
+                HeaderValue::from_str(project)?,
             );
             headers.insert(
                 "Content-Type",
-                HeaderValue::from_str("application/json; charset=utf-8").unwrap(),
+                HeaderValue::from_static("application/json; charset=utf-8"),
             );
 
-            Self {
+            Ok(Self {
                 client: Client::new(),
                 headers,
-                token,
+                token_provider,
+                voices: voices.clone(),
+            })
+        }
+
+        /// Posts a JSON request, fetching a fresh access token and retrying once if the
+        /// current one is rejected.
+        async fn post_json(
+            &self,
+            provider: &'static str,
+            url: &str,
+            request: &serde_json::Value,
+        ) -> AppResult<serde_json::Value> {
+            let mut token_refreshed = false;
+
+            loop {
+                let token = self.token_provider.access_token().await?;
+                let response = self
+                    .client
+                    .post(url)
+                    .headers(self.headers.clone())
+                    .bearer_auth(token)
+                    .json(request)
+                    .send()
+                    .await?;
+
+                if response.status() == reqwest::StatusCode::UNAUTHORIZED
+                    && !token_refreshed
+                    && self.token_provider.invalidate().await
+                {
+                    token_refreshed = true;
+                    continue;
+                }
+
+                return Ok(check_status(provider, response)
+                    .await?
+                    .json::<serde_json::Value>()
+                    .await?);
             }
         }
 
@@ -83,7 +85,7 @@ pub mod google_client {
             &self,
             buffer: Vec<u8>,
             usage_options: &UsageOptions,
-        ) -> Result<String, Box<dyn std::error::Error>> {
+        ) -> AppResult<Vec<InterpretedLine>> {
             let encoded_buffer = BASE64_STANDARD.encode(&buffer);
 
             let request = json!({
@@ -94,83 +96,110 @@ pub mod google_client {
             });
 
             let json_response = self
-                .client
-                .post("https://vision.googleapis.com/v1/images:annotate")
-                .headers(self.headers.clone())
-                .bearer_auth(self.token.trim())
-                .json(&request)
-                .send()
-                .await?
-                .json::<serde_json::Value>()
+                .post_json(
+                    "Google Vision",
+                    "https://vision.googleapis.com/v1/images:annotate",
+                    &request,
+                )
                 .await?;
 
-            let mut extracted_text = String::with_capacity(100);
+            let mut interpreted_lines = Vec::with_capacity(5);
             if let Some(responses) = json_response["responses"].as_array() {
-                responses.iter().for_each(|response| {
-                    if let Some(full_annotation) = response["fullTextAnnotation"]["text"].as_str() {
-                        extracted_text.push_str(full_annotation);
+                for response in responses {
+                    // Vision reports per-image failures inside an otherwise successful response
+                    if response["error"].is_object() {
+                        let (code, message) = service_error(response);
+                        return Err(AppError::HttpStatus {
+                            provider: "Google Vision",
+                            status: 200,
+                            code,
+                            message: message.unwrap_or_default(),
+                        });
                     }
-                });
+
+                    interpreted_lines.extend(annotation_lines(&response["fullTextAnnotation"]));
+                }
+            }
+
+            if usage_options.debug_printing {
+                interpreted_lines.iter().for_each(|line| println!("{}", line));
             }
 
-            Ok(extracted_text)
+            Ok(interpreted_lines)
         }
 
         pub async fn make_tts_request(
             &self,
-            text: &String,
+            text: &str,
             language: TextToSpeechLanguage,
-        ) -> Result<(), Box<dyn std::error::Error>> {
+        ) -> AppResult<SynthesizedAudio> {
+            let voice_name = self
+                .voices
+                .get(&language.to_string())
+                .map(String::as_str)
+                .unwrap_or(match language {
+                    Japanese => "ja-JP-Chirp3-HD-Achernar",
+                    English => "en-US-Chirp3-HD-Achernar",
+                    Finnish => "fi-FI-Chirp3-HD-Achernar",
+                    Swedish => "sv-SE-Chirp3-HD-Achernar",
+                });
+            let language_code = voice_locale(voice_name);
+
             let request = json!({
                 "input": {
                     "markup": text
                 },
                 "voice": {
-                    "languageCode": "ja-JP",
-                    "name": "ja-JP-Chirp3-HD-Achernar",
+                    "languageCode": language_code,
+                    "name": voice_name,
                     "voiceClone": {}
                 },
                 "audioConfig": {
-                    "audioEncoding": "MP3"
+                    "audioEncoding": "MP3",
+                    "sampleRateHertz": TTS_SAMPLE_RATE
                 }
             });
 
             let json_response = self
-                .client
-                .post("https://texttospeech.googleapis.com/v1/text:synthesize")
-                .headers(self.headers.clone())
-                .bearer_auth(self.token.trim())
-                .json(&request)
-                .send()
-                .await?
-                .json::<serde_json::Value>()
+                .post_json(
+                    "Google Text-to-Speech",
+                    "https://texttospeech.googleapis.com/v1/text:synthesize",
+                    &request,
+                )
                 .await?;
 
-            if let Some(audio_content) = json_response["audioContent"].as_str() {
-                let decoded_audio = BASE64_STANDARD.decode(audio_content.as_bytes())?;
+            let audio_content = json_response["audioContent"]
+                .as_str()
+                .ok_or_else(|| {
+                    AppError::Parse("No audio content in text-to-speech response".to_string())
+                })?;
 
-                // Save the audio to a file
-                let mut file =
-                    File::create("output_audio.mp3").expect("Failed to create audio file");
-                let _ = file
-                    .write_all(decoded_audio.as_slice())
-                    .expect("Failed to write to file");
-            }
-
-            Ok(())
+            Ok(SynthesizedAudio {
+                data: BASE64_STANDARD.decode(audio_content.as_bytes())?,
+                codec: AudioCodec::Mp3,
+                sample_rate: TTS_SAMPLE_RATE,
+            })
         }
 
         pub async fn make_trans_request(
             &self,
-            text: &String,
+            text: &str,
             output_languages: &[TextToSpeechLanguage],
-        ) -> Result<TranslationResponse, Box<dyn std::error::Error>> {
+        ) -> AppResult<TranslationResponse> {
+            let mut translation_response = TranslationResponse {
+                en_translation: String::new(),
+                fi_translation: String::new(),
+                sv_translation: String::new(),
+            };
+
             let language_code = if output_languages.contains(&English) {
                 "en"
             } else if output_languages.contains(&Finnish) {
                 "fi"
-            } else {
+            } else if output_languages.contains(&Swedish) {
                 "sv"
+            } else {
+                return Ok(translation_response);
             };
 
             let request = json!({
@@ -181,14 +210,11 @@ pub mod google_client {
             });
 
             let json_response = self
-                .client
-                .post("https://translation.googleapis.com/language/translate/v2")
-                .headers(self.headers.clone())
-                .bearer_auth(self.token.trim())
-                .json(&request)
-                .send()
-                .await?
-                .json::<serde_json::Value>()
+                .post_json(
+                    "Google Translate",
+                    "https://translation.googleapis.com/language/translate/v2",
+                    &request,
+                )
                 .await?;
 
             let mut cum_translation = String::with_capacity(100);
@@ -201,17 +227,233 @@ pub mod google_client {
                 });
             }
 
-            Ok(TranslationResponse {
-                en_translation: cum_translation,
-                fi_translation: "".to_string(),
-                sv_translation: "".to_string(),
+            match language_code {
+                "en" => translation_response.en_translation = cum_translation,
+                "fi" => translation_response.fi_translation = cum_translation,
+                _ => translation_response.sv_translation = cum_translation,
+            }
+
+            Ok(translation_response)
+        }
+    }
+
+    /// The lines of a Vision text annotation, which nests pages, blocks, paragraphs, words
+    /// and symbols. A paragraph is split into lines where Vision detected a line break,
+    /// and the space or hyphen at such a break is left to joining the lines again.
+    fn annotation_lines(annotation: &serde_json::Value) -> Vec<InterpretedLine> {
+        let mut lines = Vec::new();
+        let blocks = array(&annotation["pages"]).flat_map(|page| array(&page["blocks"]));
+
+        for (block, block_annotation) in blocks.enumerate() {
+            for paragraph in array(&block_annotation["paragraphs"]) {
+                let mut words = Vec::new();
+                for word in array(&paragraph["words"]) {
+                    words.push(word);
+
+                    let last_break = array(&word["symbols"])
+                        .last()
+                        .and_then(|symbol| symbol["property"]["detectedBreak"]["type"].as_str());
+                    if matches!(last_break, Some("EOL_SURE_SPACE" | "HYPHEN" | "LINE_BREAK")) {
+                        lines.extend(line_from_words(&words, block));
+                        words.clear();
+                    }
+                }
+                lines.extend(line_from_words(&words, block));
+            }
+        }
+
+        lines
+    }
+
+    /// One line made of `words`, boxed by the union of their bounding boxes and as
+    /// confident as they are on average. `None` when the words hold no text.
+    fn line_from_words(words: &[&serde_json::Value], block: usize) -> Option<InterpretedLine> {
+        let (mut left, mut top, mut right, mut bottom) = (i32::MAX, i32::MAX, i32::MIN, i32::MIN);
+        for vertex in words.iter().flat_map(|word| array(&word["boundingBox"]["vertices"])) {
+            // Vision leaves out coordinates that are zero
+            let x = vertex["x"].as_i64().unwrap_or(0) as i32;
+            let y = vertex["y"].as_i64().unwrap_or(0) as i32;
+            (left, top, right, bottom) = (left.min(x), top.min(y), right.max(x), bottom.max(y));
+        }
+
+        let mut text = String::with_capacity(50);
+        for symbol in words.iter().flat_map(|word| array(&word["symbols"])) {
+            text.push_str(symbol["text"].as_str().unwrap_or_default());
+            match symbol["property"]["detectedBreak"]["type"].as_str() {
+                Some("SPACE" | "SURE_SPACE") => text.push(' '),
+                // The hyphen of a word broken over two lines is not one of the symbols
+                Some("HYPHEN") => text.push('-'),
+                _ => {}
+            }
+        }
+
+        let text = text.trim();
+        if text.is_empty() || left > right {
+            return None;
+        }
+
+        let confidences: Vec<f64> = words.iter().filter_map(|word| word["confidence"].as_f64()).collect();
+
+        let mut line = InterpretedLine::new(left, top, right - left, bottom - top);
+        line.block = block;
+        line.confidence = (!confidences.is_empty())
+            .then(|| (confidences.iter().sum::<f64>() / confidences.len() as f64) as f32);
+        line.text.push_str(text);
+        Some(line)
+    }
+
+    fn array(value: &serde_json::Value) -> impl Iterator<Item = &serde_json::Value> {
+        value.as_array().into_iter().flatten()
+    }
+
+    #[async_trait(?Send)]
+    impl OcrEngine for GoogleCloudClient {
+        async fn recognize(
+            &self,
+            image: Vec<u8>,
+            usage_options: &UsageOptions,
+        ) -> AppResult<Vec<InterpretedLine>> {
+            self.make_ocr_request(image, usage_options).await
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl Translator for GoogleCloudClient {
+        async fn translate(
+            &self,
+            text: &str,
+            output_languages: &[TextToSpeechLanguage],
+        ) -> AppResult<TranslationResponse> {
+            self.make_trans_request(text, output_languages).await
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl SpeechSynthesizer for GoogleCloudClient {
+        async fn synthesize(
+            &self,
+            text: &str,
+            language: TextToSpeechLanguage,
+        ) -> AppResult<SynthesizedAudio> {
+            self.make_tts_request(text, language).await
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::google_auth::google_auth::tests::{service_account_provider, stand_in, token_response};
+
+        /// A word as Vision returns it, one symbol per character and the break after the last.
+        fn word(text: &str, detected_break: Option<&str>, bounds: [i32; 4]) -> serde_json::Value {
+            let [left, top, right, bottom] = bounds;
+            let count = text.chars().count();
+            let symbols: Vec<_> = text
+                .chars()
+                .enumerate()
+                .map(|(index, c)| match detected_break.filter(|_| index == count - 1) {
+                    Some(kind) => json!({
+                        "text": c.to_string(),
+                        "property": { "detectedBreak": { "type": kind } },
+                    }),
+                    None => json!({ "text": c.to_string() }),
+                })
+                .collect();
+            // Vision leaves out coordinates that are zero
+            let vertex = |x: i32, y: i32| match x {
+                0 => json!({ "y": y }),
+                x => json!({ "x": x, "y": y }),
+            };
+
+            json!({
+                "boundingBox": {
+                    "vertices": [vertex(left, top), vertex(right, top), vertex(right, bottom), vertex(left, bottom)],
+                },
+                "confidence": 0.9,
+                "symbols": symbols,
             })
         }
 
-        fn extract_google_project(config: &str) -> Option<&str> {
-            config
-                .find("project = ")
-                .map(|project_start| config.split_at(project_start + 10).1)
+        fn annotation() -> serde_json::Value {
+            json!({
+                "pages": [{
+                    "blocks": [
+                        { "paragraphs": [{ "words": [word("Ren", Some("LINE_BREAK"), [0, 10, 60, 40])] }] },
+                        { "paragraphs": [{ "words": [
+                            word("Hello,", Some("SPACE"), [0, 50, 100, 80]),
+                            word("world", Some("EOL_SURE_SPACE"), [110, 50, 200, 80]),
+                            word("transla", Some("HYPHEN"), [0, 90, 150, 120]),
+                            word("tion", None, [0, 130, 80, 160]),
+                        ] }] },
+                    ],
+                }],
+            })
+        }
+
+        #[test]
+        fn splits_paragraphs_at_line_breaks() {
+            let lines = annotation_lines(&annotation());
+            let texts: Vec<_> = lines.iter().map(|line| line.text.as_str()).collect();
+            assert_eq!(texts, ["Ren", "Hello, world", "transla-", "tion"]);
+        }
+
+        #[test]
+        fn numbers_the_blocks() {
+            let blocks: Vec<_> = annotation_lines(&annotation()).iter().map(|line| line.block).collect();
+            assert_eq!(blocks, [0, 1, 1, 1]);
+        }
+
+        #[test]
+        fn boxes_lines_around_their_words() {
+            let line = &annotation_lines(&annotation())[1];
+            assert_eq!((line.x, line.y, line.width, line.height), (0, 50, 200, 30));
+            assert_eq!(line.confidence, Some(0.9));
+        }
+
+        #[test]
+        fn skips_empty_annotations() {
+            assert!(annotation_lines(&json!({})).is_empty());
+            let no_words = json!({ "pages": [{ "blocks": [{ "paragraphs": [{ "words": [] }] }] }] });
+            assert!(annotation_lines(&no_words).is_empty());
+        }
+
+        fn unauthorized() -> (u16, serde_json::Value) {
+            (401, json!({ "error": { "code": 401, "message": "Request had invalid authentication credentials.", "status": "UNAUTHENTICATED" } }))
+        }
+
+        #[tokio::test]
+        async fn retries_with_a_new_token_when_the_first_is_rejected() {
+            let stand_in = stand_in(vec![
+                token_response("token-1", 3600),
+                unauthorized(),
+                token_response("token-2", 3600),
+                (200, json!({ "ok": true })),
+            ])
+            .await;
+            let client = GoogleCloudClient::new(service_account_provider(&stand_in), "project", &HashMap::new()).unwrap();
+
+            let response = client.post_json("Google", &format!("{}/api", stand_in.url), &json!({})).await.unwrap();
+            assert_eq!(response, json!({ "ok": true }));
+
+            let requests = stand_in.requests.lock().unwrap();
+            assert!(requests[1].contains("authorization: bearer token-1"));
+            assert!(requests[3].contains("authorization: bearer token-2"));
+            assert!(requests[3].contains("x-goog-user-project: project"));
+        }
+
+        #[tokio::test]
+        async fn retries_a_rejected_token_only_once() {
+            let stand_in = stand_in(vec![
+                token_response("token-1", 3600),
+                unauthorized(),
+                token_response("token-2", 3600),
+                unauthorized(),
+            ])
+            .await;
+            let client = GoogleCloudClient::new(service_account_provider(&stand_in), "project", &HashMap::new()).unwrap();
+
+            let response = client.post_json("Google", &format!("{}/api", stand_in.url), &json!({})).await;
+            assert!(matches!(response, Err(AppError::HttpStatus { status: 401, .. })));
         }
     }
 }
@@ -1,53 +1,329 @@
 mod audio_player;
 mod azure_clients;
+mod batch;
+mod cache;
 mod camera_capture;
+mod change_detection;
+mod cli;
+mod config;
+mod dedup;
+mod dialogue_detection;
+mod error;
+mod frame_source;
+mod google_auth;
 mod google_client;
+mod perspective;
+mod pipeline;
+mod preprocessing;
+mod providers;
+mod regions;
+mod report;
+mod speaker_detection;
 mod utils;
+mod video_source;
 
 use std::fs::File;
+use std::path::Path;
 
 use crate::audio_player::audio_player::AudioPlayer;
-use crate::azure_clients::azure_clients::{
-    AzureOcrClient, AzureTextToSpeechClient, AzureTranslatorClient,
-};
-use crate::camera_capture::camera_capture::CameraCapture;
-use crate::google_client::google_client::GoogleCloudClient;
+use crate::batch::batch::{collect_images, failures, print_results as print_batch_results, process_images, BatchOptions};
+use crate::camera_capture::camera_capture::{list_cameras, CameraBackend, FrameProcessor};
+use crate::change_detection::change_detection::{thumbnail, ChangeDetector};
+use crate::cli::cli::{print_results, print_translations, Cli, Command, OutputFormat};
+use crate::config::config::Config;
+use crate::dedup::dedup::{CaptureResult, Deduplicator};
+use crate::error::error::{with_retries, AppError, AppResult};
+use crate::frame_source::frame_source::{open_source, FrameSource, SourceSpec};
+use crate::perspective::perspective::{corners_to_config, detect_screen};
+use crate::pipeline::pipeline::Pipeline;
+use crate::providers::providers::SpeechSynthesizer;
+use crate::regions::regions::{Profile, Region};
+use crate::report::report::write_report;
 use crate::utils::utils::TextToSpeechLanguage::{English, Finnish, Japanese, Swedish};
-use crate::utils::utils::UsageOptions;
+use crate::utils::utils::{DialogueLine, SynthesizedAudio, TextToSpeechLanguage, UsageOptions};
+use crate::video_source::video_source::{transcribe, VideoSource};
+use clap::Parser;
+use opencv::core::Mat;
 use std::io::{Read};
+use std::task::Poll;
+use std::time::Duration;
 use tokio;
 
-const QUERY_MESSAGE: &str = "Press enter to capture, q-enter to quit, [fethdcEFS]-enter to toggle mode:";
+const QUERY_MESSAGE: &str = "Press enter to capture, q-enter to quit, [fethadcEFS]-enter to toggle mode, :help-enter for more commands:";
+const COMMAND_HELP: &str = ":regions lists the regions, :region NAME selects one, :region NAME x,y,w,h[px] defines one, :profile NAME loads a profile, :save NAME saves the regions as a profile, :steps lists the preprocessing steps, :step N turns one on or off";
 
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok(); // Load settings from .env file into environment variables
+    let cli = Cli::parse();
 
-    let mut camera = CameraCapture::new(3840, 2160);
-    let azure_ocr_client = AzureOcrClient::new();
-    let google_cloud_client = GoogleCloudClient::new();
-    let azure_text_to_speech_client = AzureTextToSpeechClient::new();
-    let azure_translator_client = AzureTranslatorClient::new();
-    let audio_player = AudioPlayer::new();
+    if let Err(e) = run(cli).await {
+        report_error(&e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> AppResult<()> {
+    let overrides = cli.overrides();
+    let command = cli.command.unwrap_or(Command::Run);
+    // Listing the cameras only needs the backend, so it works before the rest is set up
+    let config = match command {
+        Command::ListCameras => Config::load_unchecked(&overrides)?,
+        _ => Config::load(&overrides)?,
+    };
+
+    let mut usage_options = config.usage_options();
+    // Only the commands that need them set up the providers and load the regions
+    let build_pipeline = || Pipeline::from_config(&config);
+    let select_profile = || -> AppResult<Profile> {
+        let mut profile = config.profile()?;
+        if let Some(name) = &cli.region {
+            profile.select(name)?;
+        }
+        Ok(profile)
+    };
+
+    match command {
+        Command::ListCameras => print_cameras(config.camera.backend, cli.format),
+        Command::Calibrate { save } => {
+            let mut source = open_source(cli.input.as_ref(), &config)?;
+            calibrate(source.as_mut(), &config, save.as_deref(), cli.format)
+        }
+        Command::Run => {
+            // The interactive loop reads its commands from stdin
+            if let Some(SourceSpec::Stdin) = cli.input {
+                return Err(AppError::Config(
+                    "stdin cannot be the input of the interactive loop, use the capture subcommand".to_string(),
+                ));
+            }
+
+            let source = open_source(cli.input.as_ref(), &config)?;
+            let pipeline = build_pipeline()?;
+            interactive_loop(source, &config, &pipeline, select_profile()?, usage_options, cli.format).await
+        }
+        Command::Capture { mute } => {
+            let pipeline = build_pipeline()?;
+            let profile = select_profile()?;
+            let region = active_region(&profile, &usage_options);
+            let mut source = open_source(cli.input.as_ref(), &config)?;
+            let processor = FrameProcessor::new(&config);
+            let audio_player = if mute { None } else { Some(AudioPlayer::new()?) };
+            let session = Session {
+                processor: &processor,
+                pipeline: &pipeline,
+                audio_player: audio_player.as_ref(),
+                format: cli.format,
+            };
+            let mut deduplicator = Deduplicator::new(&config.dedup);
+
+            capture_process_playback(source.as_mut(), &session, region, &usage_options, &mut deduplicator).await
+        }
+        Command::Watch { mute } => {
+            let pipeline = build_pipeline()?;
+            let profile = select_profile()?;
+            let region = active_region(&profile, &usage_options);
+            let mut source = open_source(cli.input.as_ref(), &config)?;
+            let processor = FrameProcessor::new(&config);
+            let audio_player = if mute { None } else { Some(AudioPlayer::new()?) };
+            let session = Session {
+                processor: &processor,
+                pipeline: &pipeline,
+                audio_player: audio_player.as_ref(),
+                format: cli.format,
+            };
+
+            watch(source.as_mut(), &session, &config, region, &usage_options).await
+        }
+        Command::Ocr { image } => {
+            let pipeline = build_pipeline()?;
+            let line = pipeline.recognize(load_image_from_disk(&image)?, &usage_options).await?;
+
+            print_results(cli.format, &line, None)
+        }
+        Command::Translate { text } => {
+            let pipeline = build_pipeline()?;
+            let text = text_or_stdin(text)?;
+            usage_options.use_translation = true;
+            let translated_text = pipeline
+                .translator
+                .translate(&text, &output_languages(&usage_options))
+                .await?;
+
+            let line = DialogueLine {
+                text,
+                ..DialogueLine::default()
+            };
+            print_results(cli.format, &line, Some(&translated_text))
+        }
+        Command::Batch { inputs, jobs, report, raw } => {
+            let images = collect_images(&inputs)?;
+            if images.is_empty() {
+                return Err(AppError::Io(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "No images found in the given paths",
+                )));
+            }
+
+            let pipeline = build_pipeline()?;
+            let profile = select_profile()?;
+            let region = active_region(&profile, &usage_options);
+            let processor = FrameProcessor::new(&config);
+            let languages = output_languages(&usage_options);
+            let options = BatchOptions {
+                jobs,
+                raw,
+                region,
+                languages: &languages,
+            };
+            let results = process_images(&images, &processor, &pipeline, &usage_options, &options).await;
+
+            match report {
+                Some(path) => write_report(&path, &results)?,
+                None => print_batch_results(&results, cli.format)?,
+            }
+
+            let failed = failures(&results);
+            if failed > 0 {
+                return Err(AppError::Io(std::io::Error::other(format!(
+                    "{} of {} images could not be processed",
+                    failed,
+                    results.len()
+                ))));
+            }
+
+            Ok(())
+        }
+        Command::Video { file, report, .. } => {
+            let pipeline = build_pipeline()?;
+            let profile = select_profile()?;
+            let region = active_region(&profile, &usage_options);
+            let mut source = VideoSource::open(&file, &config.video)?;
+            let processor = FrameProcessor::new(&config);
+            let languages = output_languages(&usage_options);
+
+            let transcript = transcribe(
+                &mut source,
+                &processor,
+                region,
+                &pipeline,
+                &usage_options,
+                &languages,
+                cli.format,
+            )
+            .await?;
+
+            if let Some(path) = report {
+                write_report(&path, &transcript)?;
+            }
+
+            let failed = transcript.iter().filter(|entry| entry.error.is_some()).count();
+            if failed > 0 {
+                eprintln!("{} of {} frames could not be processed", failed, transcript.len());
+            }
+
+            Ok(())
+        }
+        Command::Speak { text, language, save } => {
+            let pipeline = build_pipeline()?;
+            let text = text_or_stdin(text)?;
+            let audio = pipeline.speech_synthesizer.synthesize(&text, language).await?;
+
+            match save {
+                Some(path) => Ok(std::fs::write(path, &audio.data)?),
+                None => AudioPlayer::new()?.play_audio(&audio).await,
+            }
+        }
+    }
+}
+
+/// The region captures are cropped to, `None` when cropping is off.
+fn active_region<'a>(profile: &'a Profile, usage_options: &UsageOptions) -> Option<&'a Region> {
+    usage_options.use_region.then(|| profile.active_region()).flatten()
+}
+
+fn print_cameras(backend: CameraBackend, format: OutputFormat) -> AppResult<()> {
+    let cameras = list_cameras(backend)?;
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string(&cameras)?),
+        OutputFormat::Text if cameras.is_empty() => println!("No cameras found"),
+        OutputFormat::Text => {
+            for camera in cameras {
+                println!(
+                    "{} ({}): {}",
+                    camera.device,
+                    camera.backend,
+                    camera.resolutions.join(", ")
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the corners of the screen found in the next frame as a `[perspective]` section
+/// that can be pasted into config.toml.
+fn calibrate(
+    source: &mut dyn FrameSource,
+    config: &Config,
+    save: Option<&Path>,
+    format: OutputFormat,
+) -> AppResult<()> {
+    let frame = next_frame(source)?;
+    if let Some(path) = save {
+        opencv::imgcodecs::imwrite(&path.to_string_lossy(), &frame, &opencv::core::Vector::new())?;
+        eprintln!("Saved the frame to {}", path.display());
+    }
+
+    let corners = detect_screen(&frame, &config.perspective)?;
+    let corners = corners.as_ref().map(corners_to_config);
+    match (format, corners) {
+        (OutputFormat::Json, corners) => println!("{}", serde_json::json!({ "corners": corners })),
+        (OutputFormat::Text, Some(corners)) => {
+            println!("[perspective]\ncorners = {:?}", corners);
+        }
+        (OutputFormat::Text, None) => println!(
+            "No screen found. Try a lower perspective.edge_threshold, or save the frame with --save and enter the corners by hand"
+        ),
+    }
+
+    Ok(())
+}
+
+async fn interactive_loop(
+    mut source: Box<dyn FrameSource>,
+    config: &Config,
+    pipeline: &Pipeline,
+    mut profile: Profile,
+    mut usage_options: UsageOptions,
+    format: OutputFormat,
+) -> AppResult<()> {
+    let mut processor = FrameProcessor::new(config);
+    let audio_player = AudioPlayer::new()?;
+    let mut deduplicator = Deduplicator::new(&config.dedup);
 
     use text_io::read;
 
     println!("{}", QUERY_MESSAGE);
     let mut line: String = read!("{}\n");
 
-    let mut usage_options = UsageOptions {
-        playback_en: false,
-        playback_fi: false,
-        use_translation: true,
-        translate_en: false,
-        translate_fi: false,
-        translate_sv: true,
-        half_screen: true,
-        debug_printing: false,
-        color_correction: false,
-    };
+    loop {
+        // These commands change how frames are captured, so they do not capture themselves
+        if let Some(command) = line.trim().strip_prefix(':') {
+            if let Err(e) = run_command(command, &mut profile, &mut processor, config) {
+                report_error(&e);
+            }
+
+            println!("{}", QUERY_MESSAGE);
+            line = read!("{}\n");
+            continue;
+        }
+
+        if line.contains("q") {
+            break;
+        }
 
-    while !line.contains("q") {
         if line.contains("f") {
             usage_options.playback_fi = !usage_options.playback_fi
         };
@@ -58,7 +334,10 @@ async fn main() {
             usage_options.use_translation = !usage_options.use_translation
         };
         if line.contains("h") {
-            usage_options.half_screen = !usage_options.half_screen
+            usage_options.use_region = !usage_options.use_region
+        };
+        if line.contains("a") {
+            usage_options.detect_dialogue = !usage_options.detect_dialogue
         };
         if line.contains("d") {
             usage_options.debug_printing = !usage_options.debug_printing
@@ -85,50 +364,266 @@ async fn main() {
             usage_options.translate_sv = true;
         }
 
-        match capture_process_playback(
-            &mut camera,
-            &azure_ocr_client,
-            &azure_text_to_speech_client,
-            &azure_translator_client,
-            &google_cloud_client,
-            &audio_player,
-            &usage_options,
-        )
-        .await {
-            Ok(_) => (),
-            Err(e) => eprintln!("{}", e),
+        let region = active_region(&profile, &usage_options);
+        let session = Session {
+            processor: &processor,
+            pipeline,
+            audio_player: Some(&audio_player),
+            format,
+        };
+        // The requests retry by themselves, so a failed capture is not taken again
+        if let Err(e) =
+            capture_process_playback(source.as_mut(), &session, region, &usage_options, &mut deduplicator).await
+        {
+            report_error(&e);
         }
 
         println!("{}", QUERY_MESSAGE);
         line = read!("{}\n");
     }
+
+    Ok(())
+}
+
+/// Handles the `:` commands of the interactive loop that list, select and define regions
+/// of interest, load or save them as a game profile and toggle preprocessing steps.
+fn run_command(
+    command: &str,
+    profile: &mut Profile,
+    processor: &mut FrameProcessor,
+    config: &Config,
+) -> AppResult<()> {
+    let mut words = command.split_whitespace();
+
+    match (words.next(), words.next(), words.next()) {
+        (Some("regions"), None, None) => {
+            for (name, region) in &profile.named {
+                let marker = if *name == profile.active { "*" } else { " " };
+                println!("{} {}: {:?}", marker, name, region);
+            }
+        }
+        (Some("region"), Some(name), None) => {
+            profile.select(name)?;
+            println!("Cropping to region '{}'", name);
+        }
+        (Some("region"), Some(name), Some(region)) => {
+            let region = region.parse::<Region>().map_err(AppError::Config)?;
+            profile.named.insert(name.to_string(), region);
+            profile.select(name)?;
+            println!("Cropping to new region '{}'", name);
+        }
+        (Some("profile"), Some(name), None) => {
+            *profile = config.load_profile(Some(name))?;
+            println!("Loaded profile '{}', cropping to region '{}'", name, profile.active);
+        }
+        (Some("save"), Some(name), None) => {
+            let path = profile.save(Path::new(&config.regions.profiles_dir), name)?;
+            println!("Saved profile to {}", path.display());
+        }
+        (Some("steps"), None, None) => {
+            for (index, step) in processor.steps().iter().enumerate() {
+                println!("{}: {}", index, step);
+            }
+        }
+        (Some("step"), Some(index), None) => {
+            let index = index
+                .parse::<usize>()
+                .map_err(|e| AppError::Config(format!("Invalid step '{}': {}", index, e)))?;
+            println!("{}", processor.toggle_step(index)?);
+        }
+        _ => println!("{}", COMMAND_HELP),
+    }
+
+    Ok(())
+}
+
+fn report_error(error: &AppError) {
+    eprintln!("{}", error);
+    if let Some(hint) = error.hint() {
+        eprintln!("{}", hint);
+    }
+}
+
+/// What a capture is sent through once it is taken, shared by the run, capture and
+/// watch subcommands.
+struct Session<'a> {
+    processor: &'a FrameProcessor,
+    pipeline: &'a Pipeline,
+    audio_player: Option<&'a AudioPlayer>,
+    format: OutputFormat,
 }
 
 async fn capture_process_playback(
-    camera: &mut CameraCapture,
-    azure_ocr_client: &AzureOcrClient,
-    azure_text_to_speech_client: &AzureTextToSpeechClient,
-    azure_translator_client: &AzureTranslatorClient,
-    google_cloud_client: &GoogleCloudClient,
-    audio_player: &AudioPlayer,
+    source: &mut dyn FrameSource,
+    session: &Session<'_>,
+    region: Option<&Region>,
+    usage_options: &UsageOptions,
+    deduplicator: &mut Deduplicator,
+) -> AppResult<()> {
+    let frame = next_frame(source)?;
+    let cropped = session.processor.crop(frame, region, usage_options)?;
+
+    process_playback(session, cropped, usage_options, deduplicator).await
+}
+
+/// Recognizes, translates and reads aloud a cropped frame, repeating only the request that
+/// failed when a provider has a passing problem. A frame or text that is the same as last
+/// time replays the last result instead.
+async fn process_playback(
+    session: &Session<'_>,
+    cropped: Mat,
     usage_options: &UsageOptions,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let image_buffer = if use_test_file().parse()? {
-        load_image_from_disk()?
-    } else {
-        camera.capture_image(usage_options.half_screen, usage_options.color_correction)?
+    deduplicator: &mut Deduplicator,
+) -> AppResult<()> {
+    let corrected = session.processor.correct(cropped, usage_options)?;
+    let frame = thumbnail(&corrected)?;
+    let languages = output_languages(usage_options);
+    if let Some(previous) = deduplicator.by_frame(&frame, &languages)? {
+        return replay(session, previous, usage_options).await;
+    }
+
+    let pipeline = session.pipeline;
+    let image = session.processor.encode(&corrected)?;
+    let line = with_retries(|| pipeline.recognize(image.clone(), usage_options)).await?;
+    let extracted_text = line.to_string();
+    if let Some(previous) = deduplicator.by_text(&frame, &extracted_text, &languages) {
+        return replay(session, previous, usage_options).await;
+    }
+
+    if session.format == OutputFormat::Text {
+        println!("{}\n", &extracted_text);
+    }
+
+    let translated_text_future = with_retries(|| pipeline.translate_dialogue(&line, languages.as_slice()));
+
+    let speech_synthesizer = pipeline.speech_synthesizer.as_ref();
+    let mut audio = Vec::new();
+    if let Some(audio_player) = session.audio_player {
+        let speech = speak(speech_synthesizer, audio_player, &extracted_text, Japanese, usage_options).await?;
+        audio.push((Japanese, speech));
+    }
+
+    let translated_text = translated_text_future.await?;
+
+    match session.format {
+        OutputFormat::Text => print_translations(&translated_text),
+        OutputFormat::Json => print_results(session.format, &line, Some(&translated_text))?,
+    }
+
+    if let Some(audio_player) = session.audio_player {
+        for (language, translation, playback) in [
+            (English, &translated_text.en_translation, usage_options.playback_en),
+            (Finnish, &translated_text.fi_translation, usage_options.playback_fi),
+        ] {
+            if !translation.is_empty() && playback {
+                let speech = speak(speech_synthesizer, audio_player, translation, language, usage_options).await?;
+                audio.push((language, speech));
+            }
+        }
+    }
+
+    deduplicator.remember(
+        frame,
+        CaptureResult {
+            line,
+            languages,
+            translations: translated_text,
+            audio,
+        },
+    );
+
+    Ok(())
+}
+
+/// Shows the last result again and plays the speech that was recorded for it, leaving out
+/// translations whose playback has been turned off since.
+async fn replay(session: &Session<'_>, result: &CaptureResult, usage_options: &UsageOptions) -> AppResult<()> {
+    eprintln!("Unchanged since the last capture, replaying it");
+
+    match session.format {
+        OutputFormat::Text => {
+            println!("{}\n", &result.line);
+            print_translations(&result.translations);
+        }
+        OutputFormat::Json => print_results(session.format, &result.line, Some(&result.translations))?,
+    }
+
+    let Some(audio_player) = session.audio_player else {
+        return Ok(());
     };
 
-    let extracted_text = google_cloud_client
-        .make_ocr_request(image_buffer, &usage_options)
-        .await?;
+    for (language, audio) in &result.audio {
+        let playback = match language {
+            English => usage_options.playback_en,
+            Finnish => usage_options.playback_fi,
+            _ => true,
+        };
+        if playback {
+            audio_player.play_audio(audio).await?;
+        }
+    }
+
+    Ok(())
+}
 
-    println!("{}\n", &extracted_text);
+/// Polls the source and runs the pipeline by itself whenever the cropped area shows new
+/// text that has finished appearing, until the source runs out or ctrl-c is pressed.
+async fn watch(
+    source: &mut dyn FrameSource,
+    session: &Session<'_>,
+    config: &Config,
+    region: Option<&Region>,
+    usage_options: &UsageOptions,
+) -> AppResult<()> {
+    let mut detector = ChangeDetector::new(&config.watch);
+    let mut deduplicator = Deduplicator::new(&config.dedup);
+    let poll_interval = Duration::from_secs_f64(config.watch.poll_interval);
+    eprintln!("Watching {} for new text, press ctrl-c to stop", source.describe());
+
+    // Polling once installs the handler, so a ctrl-c pressed during a capture is not lost
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    if let Poll::Ready(result) = futures::poll!(&mut ctrl_c) {
+        return Ok(result?);
+    }
 
+    loop {
+        // One failed capture should not end a hands-free session, neither should a frame
+        // that could not be read, e.g. an image caught while it was being written
+        match source.next_frame() {
+            Ok(Some(frame)) => {
+                let cropped = session.processor.crop(frame, region, usage_options)?;
+                if detector.observe(&cropped)? {
+                    match process_playback(session, cropped, usage_options, &mut deduplicator).await {
+                        Ok(()) => detector.handled(),
+                        Err(e) => report_error(&e),
+                    }
+                }
+            }
+            Ok(None) => break,
+            Err(e) => report_error(&e),
+        }
+
+        tokio::select! {
+            result = &mut ctrl_c => return Ok(result?),
+            _ = tokio::time::sleep(poll_interval) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn next_frame(source: &mut dyn FrameSource) -> AppResult<Mat> {
+    source.next_frame()?.ok_or_else(|| {
+        AppError::Io(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("No more frames from {}", source.describe()),
+        ))
+    })
+}
+
+fn output_languages(usage_options: &UsageOptions) -> Vec<TextToSpeechLanguage> {
     let mut languages = Vec::new();
-    if !usage_options.use_translation {
-        languages.clear();
-    };
     if usage_options.use_translation {
         if usage_options.translate_en {
             languages.push(English);
@@ -143,85 +638,47 @@ async fn capture_process_playback(
         }
     };
 
-    let translated_text_future =
-        google_cloud_client.make_trans_request(&extracted_text, languages.as_slice());
-
-    google_cloud_client
-        .make_tts_request(&extracted_text, Japanese)
-        .await?;
-
-    audio_player.play_audio("output_audio.mp3").await?;
-
-    let translated_text = translated_text_future.await?;
-
-    if !translated_text.en_translation.is_empty() {
-        println!("{}\n", &translated_text.en_translation);
-    }
-
-    if !translated_text.en_translation.is_empty() && usage_options.playback_en {
-        azure_text_to_speech_client
-            .make_request(&translated_text.en_translation, English)
-            .await?;
-        audio_player.play_audio("output_audio.mp3").await?;
-    }
-
-    if !translated_text.fi_translation.is_empty() {
-        println!("{}\n", &translated_text.fi_translation);
-    }
-
-    if !translated_text.fi_translation.is_empty() && usage_options.playback_fi {
-        azure_text_to_speech_client
-            .make_request(&translated_text.fi_translation, Finnish)
-            .await?;
-        audio_player.play_audio("output_audio.mp3").await?;
-    }
+    languages
+}
 
-    if !translated_text.sv_translation.is_empty() {
-        println!("{}\n", &translated_text.sv_translation);
+async fn speak(
+    speech_synthesizer: &dyn SpeechSynthesizer,
+    audio_player: &AudioPlayer,
+    text: &str,
+    language: TextToSpeechLanguage,
+    usage_options: &UsageOptions,
+) -> AppResult<SynthesizedAudio> {
+    let audio = with_retries(|| speech_synthesizer.synthesize(text, language)).await?;
+
+    if usage_options.debug_printing {
+        println!(
+            "Synthesized {} bytes of {:?} audio at {} Hz",
+            audio.data.len(),
+            audio.codec,
+            audio.sample_rate
+        );
     }
 
-    Ok(())
+    audio_player.play_audio(&audio).await?;
+    Ok(audio)
 }
 
-fn load_image_from_disk() -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let mut file = File::open("test_image.jpg")?;
+fn load_image_from_disk(path: &Path) -> AppResult<Vec<u8>> {
+    let mut file = File::open(path)?;
     let mut bytes_vector = Vec::new();
     file.read_to_end(&mut bytes_vector)?;
 
     Ok(bytes_vector)
 }
 
-fn azure_ocr_url() -> String {
-    dotenv::var("AZURE_OCR_URL").expect("Couldn't find environment variable AZURE_OCR_URL")
-}
-fn azure_text_to_speech_url() -> String {
-    dotenv::var("AZURE_TEXT_TO_SPEECH_URL")
-        .expect("Couldn't find environment variable AZURE_TEXT_TO_SPEECH_URL")
-}
-fn azure_ocr_key() -> String {
-    dotenv::var("AZURE_OCR_KEY").expect("Couldn't find environment variable AZURE_OCR_KEY")
-}
-fn azure_text_to_speech_key() -> String {
-    dotenv::var("AZURE_TEXT_TO_SPEECH_KEY")
-        .expect("Couldn't find environment variable AZURE_TEXT_TO_SPEECH_KEY")
-}
-fn azure_translator_url() -> String {
-    dotenv::var("AZURE_TRANSLATOR_URL")
-        .expect("Couldn't find environment variable AZURE_TRANSLATOR_URL")
-}
-fn azure_translator_key() -> String {
-    dotenv::var("AZURE_TRANSLATOR_KEY")
-        .expect("Couldn't find environment variable AZURE_TRANSLATOR_KEY")
-}
-fn azure_region() -> String {
-    dotenv::var("AZURE_REGION").expect("Couldn't find environment variable AZURE_REGION")
-}
-fn use_test_file() -> String {
-    dotenv::var("USE_TEST_FILE").expect("Couldn't find environment variable USE_TEST_FILE")
-}
-fn threshold() -> f64 {
-    dotenv::var("THRESHOLD")
-        .expect("Couldn't find THRESHOLD")
-        .parse()
-        .unwrap()
+/// Lets text arguments be piped in, e.g. from the `ocr` subcommand.
+fn text_or_stdin(text: Option<String>) -> AppResult<String> {
+    match text {
+        Some(text) => Ok(text),
+        None => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            Ok(text.trim().to_string())
+        }
+    }
 }
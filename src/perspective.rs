@@ -0,0 +1,171 @@
+pub mod perspective {
+    use opencv::core::{Mat, Point2f, Scalar, Size, Vector};
+    use opencv::prelude::MatTraitConst;
+    use crate::config::config::PerspectiveConfig;
+    use crate::dialogue_detection::dialogue_detection::find_edge_contours;
+    use crate::error::error::{AppError, AppResult};
+
+    // Polygon approximation tolerance, relative to the contour's perimeter
+    const APPROXIMATION_EPSILON: f64 = 0.02;
+
+    /// The corners of the screen in the camera frame: top-left, top-right, bottom-right
+    /// and bottom-left.
+    pub type Corners = [Point2f; 4];
+
+    pub fn corners_from_config(corners: &[[f32; 2]; 4]) -> Corners {
+        corners.map(|[x, y]| Point2f::new(x, y))
+    }
+
+    pub fn corners_to_config(corners: &Corners) -> [[f32; 2]; 4] {
+        corners.map(|corner| [corner.x.round(), corner.y.round()])
+    }
+
+    /// Warps the screen seen at `corners` into an upright rectangle, sized after the
+    /// longer of its opposite edges so no detail is lost.
+    pub fn rectify(mat: &Mat, corners: &Corners) -> AppResult<Mat> {
+        let (cols, rows) = (mat.cols() as f32, mat.rows() as f32);
+        if !corners.iter().all(|corner| (0.0..=cols).contains(&corner.x) && (0.0..=rows).contains(&corner.y)) {
+            return Err(AppError::Config(format!(
+                "The screen corners {:?} lie outside the {}x{} frame",
+                corners_to_config(corners),
+                mat.cols(),
+                mat.rows()
+            )));
+        }
+
+        let distance = |a: Point2f, b: Point2f| (a.x - b.x).hypot(a.y - b.y);
+        let [top_left, top_right, bottom_right, bottom_left] = *corners;
+        let width = distance(top_left, top_right).max(distance(bottom_left, bottom_right)).round();
+        let height = distance(top_left, bottom_left).max(distance(top_right, bottom_right)).round();
+        if width < 2.0 || height < 2.0 {
+            return Err(AppError::Config(format!(
+                "The screen corners {:?} do not enclose an area",
+                corners_to_config(corners)
+            )));
+        }
+
+        let source = Vector::<Point2f>::from_slice(corners);
+        let destination = Vector::<Point2f>::from_slice(&[
+            Point2f::new(0.0, 0.0),
+            Point2f::new(width - 1.0, 0.0),
+            Point2f::new(width - 1.0, height - 1.0),
+            Point2f::new(0.0, height - 1.0),
+        ]);
+        let transform = opencv::imgproc::get_perspective_transform(&source, &destination, opencv::core::DECOMP_LU)?;
+
+        let mut rectified = Mat::default();
+        opencv::imgproc::warp_perspective(
+            mat,
+            &mut rectified,
+            &transform,
+            Size::new(width as i32, height as i32),
+            opencv::imgproc::INTER_LINEAR,
+            opencv::core::BORDER_CONSTANT,
+            Scalar::default(),
+        )?;
+
+        Ok(rectified)
+    }
+
+    /// Looks for the screen as the largest four-cornered outline covering at least
+    /// `min_area` of the frame. `None` when there is none.
+    pub fn detect_screen(mat: &Mat, config: &PerspectiveConfig) -> AppResult<Option<Corners>> {
+        let contours = find_edge_contours(mat, config.edge_threshold)?;
+        let frame_area = mat.cols() as f64 * mat.rows() as f64;
+        let mut best: Option<(f64, Corners)> = None;
+
+        for contour in contours {
+            let mut polygon = Vector::<opencv::core::Point>::new();
+            let perimeter = opencv::imgproc::arc_length(&contour, true)?;
+            opencv::imgproc::approx_poly_dp(&contour, &mut polygon, APPROXIMATION_EPSILON * perimeter, true)?;
+            if polygon.len() != 4 {
+                continue;
+            }
+
+            let area = opencv::imgproc::contour_area(&polygon, false)?;
+            if area < config.min_area * frame_area || best.is_some_and(|(best_area, _)| area <= best_area) {
+                continue;
+            }
+
+            let points = polygon.to_vec();
+            let points = [points[0], points[1], points[2], points[3]]
+                .map(|point| Point2f::new(point.x as f32, point.y as f32));
+            if let Some(corners) = order_corners(points) {
+                best = Some((area, corners));
+            }
+        }
+
+        Ok(best.map(|(_, corners)| corners))
+    }
+
+    /// Sorts the corners of a quadrilateral into top-left, top-right, bottom-right,
+    /// bottom-left order: clockwise around their centroid, starting from the corner with
+    /// the smallest x + y. `None` when they do not make a convex quadrilateral.
+    fn order_corners(mut points: [Point2f; 4]) -> Option<Corners> {
+        let center_x = points.iter().map(|point| point.x).sum::<f32>() / 4.0;
+        let center_y = points.iter().map(|point| point.y).sum::<f32>() / 4.0;
+        // With y growing downwards, increasing angles go clockwise on the screen
+        points.sort_by(|a, b| {
+            let angle = |point: &Point2f| (point.y - center_y).atan2(point.x - center_x);
+            angle(a).total_cmp(&angle(b))
+        });
+
+        let top_left = (0..4).min_by(|&a, &b| {
+            (points[a].x + points[a].y).total_cmp(&(points[b].x + points[b].y))
+        })?;
+        points.rotate_left(top_left);
+
+        is_convex(&points).then_some(points)
+    }
+
+    /// Whether the corners make a convex quadrilateral with a non-zero area when followed
+    /// clockwise on the screen, the order `rectify` expects.
+    pub fn is_convex(corners: &Corners) -> bool {
+        (0..4).all(|index| {
+            let [a, b, c] = [0, 1, 2].map(|offset| corners[(index + offset) % 4]);
+            (b.x - a.x) * (c.y - b.y) - (b.y - a.y) * (c.x - b.x) > 0.0
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn points(coordinates: [[f32; 2]; 4]) -> [Point2f; 4] {
+            corners_from_config(&coordinates)
+        }
+
+        #[test]
+        fn orders_a_tilted_screen() {
+            let corners = order_corners(points([[90.0, 210.0], [10.0, 20.0], [200.0, 10.0], [210.0, 190.0]]));
+            assert_eq!(
+                corners.map(|corners| corners_to_config(&corners)),
+                Some([[10.0, 20.0], [200.0, 10.0], [210.0, 190.0], [90.0, 210.0]])
+            );
+        }
+
+        #[test]
+        fn orders_a_screen_turned_by_45_degrees() {
+            // The top and left corners tie on x + y, every corner must still be used once
+            let corners = order_corners(points([[100.0, 0.0], [0.0, 100.0], [200.0, 100.0], [100.0, 200.0]]));
+            assert_eq!(
+                corners.map(|corners| corners_to_config(&corners)),
+                Some([[100.0, 0.0], [200.0, 100.0], [100.0, 200.0], [0.0, 100.0]])
+            );
+        }
+
+        #[test]
+        fn rejects_degenerate_quadrilaterals() {
+            assert_eq!(order_corners(points([[0.0, 0.0], [50.0, 0.0], [100.0, 0.0], [50.0, 100.0]])), None);
+            assert_eq!(order_corners(points([[0.0, 0.0], [100.0, 0.0], [30.0, 30.0], [0.0, 100.0]])), None);
+        }
+
+        #[test]
+        fn convexity_depends_on_the_order() {
+            let clockwise = points([[0.0, 0.0], [100.0, 0.0], [100.0, 100.0], [0.0, 100.0]]);
+            let crossed = points([[0.0, 0.0], [100.0, 0.0], [0.0, 100.0], [100.0, 100.0]]);
+            assert!(is_convex(&clockwise));
+            assert!(!is_convex(&crossed));
+        }
+    }
+}
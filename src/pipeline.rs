@@ -0,0 +1,174 @@
+pub mod pipeline {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use crate::azure_clients::azure_clients::{
+        AzureOcrClient, AzureTextToSpeechClient, AzureTranslatorClient,
+    };
+    use crate::cache::cache::{CachedSpeechSynthesizer, CachedTranslator, DiskCache};
+    use crate::config::config::Config;
+    use crate::error::error::AppResult;
+    use crate::frame_source::frame_source::decode_image;
+    use crate::google_auth::google_auth::{discover_project, TokenProvider, TokenSource};
+    use crate::google_client::google_client::GoogleCloudClient;
+    use crate::providers::providers::{OcrEngine, Provider, SpeechSynthesizer, Translator};
+    use crate::speaker_detection::speaker_detection::SpeakerDetector;
+    use crate::utils::utils::{DialogueLine, TextToSpeechLanguage, TranslationResponse, UsageOptions};
+
+    /// The backends serving each stage of capture processing.
+    pub struct Pipeline {
+        pub ocr_engine: Rc<dyn OcrEngine>,
+        pub translator: Rc<dyn Translator>,
+        pub speech_synthesizer: Rc<dyn SpeechSynthesizer>,
+        speaker_detector: SpeakerDetector,
+        // Speaker names translated so far, so each character keeps one name
+        names: RefCell<HashMap<String, TranslationResponse>>,
+    }
+
+    impl Pipeline {
+        /// Builds only the clients of the configured providers, so credentials for unused
+        /// providers are never needed. One Google client is shared by all Google stages.
+        /// Translation and speech go through the disk cache when it is enabled and its
+        /// directory can be created.
+        pub fn from_config(config: &Config) -> AppResult<Self> {
+            let mut google_cloud_client = None;
+
+            let ocr_engine: Rc<dyn OcrEngine> = match config.providers.ocr {
+                Provider::Google => google_client(&mut google_cloud_client, config)?,
+                Provider::Azure => Rc::new(AzureOcrClient::new(&config.azure)?),
+            };
+            let translator: Rc<dyn Translator> = match config.providers.translation {
+                Provider::Google => google_client(&mut google_cloud_client, config)?,
+                Provider::Azure => Rc::new(AzureTranslatorClient::new(&config.azure)?),
+            };
+            let speech_synthesizer: Rc<dyn SpeechSynthesizer> = match config.providers.tts {
+                Provider::Google => google_client(&mut google_cloud_client, config)?,
+                Provider::Azure => Rc::new(AzureTextToSpeechClient::new(
+                    &config.azure,
+                    &config.voices.azure,
+                )?),
+            };
+
+            // A directory the cache cannot be created in, e.g. a read-only one, only costs the cache
+            let cache = match config.cache.enabled.then(|| DiskCache::new(&config.cache)) {
+                Some(Ok(cache)) => Some(Rc::new(cache)),
+                Some(Err(e)) => {
+                    eprintln!("Continuing without the cache in {}: {}", config.cache.directory, e);
+                    None
+                }
+                None => None,
+            };
+
+            let (translator, speech_synthesizer) = if let Some(cache) = cache {
+                let translator: Rc<dyn Translator> = Rc::new(CachedTranslator::new(
+                    translator,
+                    config.providers.translation,
+                    cache.clone(),
+                ));
+                let voices = match config.providers.tts {
+                    Provider::Google => &config.voices.google,
+                    Provider::Azure => &config.voices.azure,
+                };
+                let speech_synthesizer: Rc<dyn SpeechSynthesizer> = Rc::new(CachedSpeechSynthesizer::new(
+                    speech_synthesizer,
+                    config.providers.tts,
+                    voices,
+                    cache,
+                ));
+                (translator, speech_synthesizer)
+            } else {
+                (translator, speech_synthesizer)
+            };
+
+            Ok(Self {
+                ocr_engine,
+                translator,
+                speech_synthesizer,
+                speaker_detector: SpeakerDetector::new(&config.speaker_detection),
+                names: RefCell::new(HashMap::new()),
+            })
+        }
+
+        /// Reads the dialogue in an encoded image and who says it.
+        pub async fn recognize(&self, image: Vec<u8>, usage_options: &UsageOptions) -> AppResult<DialogueLine> {
+            let decoded = if self.speaker_detector.needs_image() {
+                Some(decode_image(&image, "The captured image")?)
+            } else {
+                None
+            };
+
+            let lines = self.ocr_engine.recognize(image, usage_options).await?;
+            self.speaker_detector.detect(lines, decoded.as_ref())
+        }
+
+        /// Translates the dialogue and, on its own, the speaker's name, which then leads
+        /// each translation as "name: dialogue". A name is translated only the first time
+        /// it is seen, so the same character is not called differently from line to line.
+        pub async fn translate_dialogue(
+            &self,
+            line: &DialogueLine,
+            languages: &[TextToSpeechLanguage],
+        ) -> AppResult<TranslationResponse> {
+            let mut translations = self.translator.translate(&line.text, languages).await?;
+            let Some(speaker) = &line.speaker else {
+                return Ok(translations);
+            };
+
+            let missing: Vec<TextToSpeechLanguage> = {
+                let names = self.names.borrow();
+                let known = names.get(speaker);
+                languages
+                    .iter()
+                    .copied()
+                    .filter(|language| known.map_or("", |known| known.get(*language)).is_empty())
+                    .collect()
+            };
+            if !missing.is_empty() {
+                let translated = self.translator.translate(speaker, &missing).await?;
+                let mut names = self.names.borrow_mut();
+                let known = names.entry(speaker.clone()).or_default();
+                for language in missing {
+                    known.set(language, translated.get(language).trim().to_string());
+                }
+            }
+
+            let names = self.names.borrow();
+            for &language in languages {
+                let translation = translations.get(language);
+                if translation.is_empty() {
+                    continue;
+                }
+
+                let name = names
+                    .get(speaker)
+                    .map(|known| known.get(language))
+                    .filter(|name| !name.is_empty())
+                    .unwrap_or(speaker);
+                let translation = format!("{}: {}", name, translation);
+                translations.set(language, translation);
+            }
+
+            Ok(translations)
+        }
+    }
+
+    fn google_client(
+        existing: &mut Option<Rc<GoogleCloudClient>>,
+        config: &Config,
+    ) -> AppResult<Rc<GoogleCloudClient>> {
+        if let Some(client) = existing {
+            return Ok(client.clone());
+        }
+
+        let token_source = TokenSource::from_config(&config.google)?;
+        let project = discover_project(config.google.project.as_deref(), &token_source)?;
+        let client = Rc::new(GoogleCloudClient::new(
+            TokenProvider::new(token_source, config.google.token_uri.clone()),
+            &project,
+            &config.voices.google,
+        )?);
+
+        *existing = Some(client.clone());
+        Ok(client)
+    }
+}
@@ -0,0 +1,453 @@
+pub mod preprocessing {
+    use std::fmt::{Display, Formatter};
+    use opencv::core::{Mat, Point, Size};
+    use opencv::prelude::{CLAHETrait, MatTraitConst};
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer};
+    use crate::error::error::AppResult;
+
+    /// One image operation of the preprocessing chain, applied in the configured order
+    /// when color correction is on.
+    #[derive(Debug, Clone)]
+    pub struct PreprocessStep {
+        pub enabled: bool,
+        pub operation: Operation,
+    }
+
+    // `enabled` sits next to the operation's own fields, which are checked for typos
+    // like every other section, so it is taken out before the operation is read
+    impl<'de> Deserialize<'de> for PreprocessStep {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let mut table = toml::Table::deserialize(deserializer)?;
+            let enabled = match table.remove("enabled") {
+                None => true,
+                Some(toml::Value::Boolean(enabled)) => enabled,
+                Some(other) => {
+                    return Err(D::Error::custom(format!("enabled must be true or false, not {}", other)));
+                }
+            };
+            let operation = Operation::deserialize(toml::Value::Table(table)).map_err(D::Error::custom)?;
+
+            Ok(Self { enabled, operation })
+        }
+    }
+
+    // Operations without parameters are empty structs, so that stray fields are rejected
+    #[derive(Debug, Clone, Deserialize)]
+    #[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+    pub enum Operation {
+        Grayscale {},
+        /// Gaussian blur with an odd `kernel` size, against noise and moiré from the camera.
+        Blur {
+            #[serde(default = "default_kernel")]
+            kernel: i32,
+        },
+        /// Turns the text white and everything else black. The fixed method compares pixels
+        /// with `value`, or `camera.threshold` without one; Otsu picks the value from the
+        /// image and the adaptive methods keep pixels that are at least `c` brighter (or
+        /// darker, for dark text) than their `block_size` neighbourhood. Set
+        /// `text = "dark"` for dark text on a light background.
+        Threshold {
+            #[serde(default)]
+            method: ThresholdMethod,
+            value: Option<f64>,
+            #[serde(default = "default_block_size")]
+            block_size: i32,
+            #[serde(default = "default_c")]
+            c: f64,
+            #[serde(default)]
+            text: TextColor,
+        },
+        Invert {},
+        /// Opening removes specks, closing fills gaps in thin or outlined glyphs.
+        Morphology {
+            operation: MorphologyOperation,
+            #[serde(default = "default_morphology_kernel")]
+            kernel: i32,
+        },
+        /// Unsharp masking, `amount` is how strongly edges are boosted.
+        Sharpen {
+            #[serde(default = "default_sharpen_amount")]
+            amount: f64,
+        },
+        /// Contrast limited adaptive histogram equalization, for unevenly lit frames.
+        Clahe {
+            #[serde(default = "default_clip_limit")]
+            clip_limit: f64,
+            #[serde(default = "default_tile_size")]
+            tile_size: i32,
+        },
+        /// Scales the image by `scale`, e.g. 2.0 to upscale small text.
+        Resize {
+            scale: f64,
+        },
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ThresholdMethod {
+        #[default]
+        Fixed,
+        Otsu,
+        AdaptiveMean,
+        AdaptiveGaussian,
+    }
+
+    /// Whether the text is lighter or darker than what surrounds it.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum TextColor {
+        #[default]
+        Light,
+        Dark,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum MorphologyOperation {
+        Open,
+        Close,
+        Dilate,
+        Erode,
+    }
+
+    fn default_kernel() -> i32 {
+        5
+    }
+
+    fn default_block_size() -> i32 {
+        31
+    }
+
+    fn default_c() -> f64 {
+        10.0
+    }
+
+    fn default_morphology_kernel() -> i32 {
+        3
+    }
+
+    fn default_sharpen_amount() -> f64 {
+        1.0
+    }
+
+    fn default_clip_limit() -> f64 {
+        2.0
+    }
+
+    fn default_tile_size() -> i32 {
+        8
+    }
+
+    /// The chain used when none is configured: the fixed threshold that isolates white text.
+    pub fn default_steps() -> Vec<PreprocessStep> {
+        let threshold = Operation::Threshold {
+            method: ThresholdMethod::Fixed,
+            value: None,
+            block_size: default_block_size(),
+            c: default_c(),
+            text: TextColor::Light,
+        };
+
+        [Operation::Grayscale {}, threshold]
+            .into_iter()
+            .map(|operation| PreprocessStep { enabled: true, operation })
+            .collect()
+    }
+
+    impl Display for PreprocessStep {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            let state = if self.enabled { "on" } else { "off" };
+            match &self.operation {
+                Operation::Grayscale {} => write!(f, "grayscale ({})", state),
+                Operation::Blur { kernel } => write!(f, "blur {} ({})", kernel, state),
+                Operation::Threshold { method, value, text, .. } => {
+                    write!(f, "threshold {:?}", method)?;
+                    if let (ThresholdMethod::Fixed, Some(value)) = (method, value) {
+                        write!(f, " {}", value)?;
+                    }
+                    write!(f, ", {:?} text ({})", text, state)
+                }
+                Operation::Invert {} => write!(f, "invert ({})", state),
+                Operation::Morphology { operation, kernel } => {
+                    write!(f, "morphology {:?} {} ({})", operation, kernel, state)
+                }
+                Operation::Sharpen { amount } => write!(f, "sharpen {} ({})", amount, state),
+                Operation::Clahe { clip_limit, tile_size } => {
+                    write!(f, "clahe {} {} ({})", clip_limit, tile_size, state)
+                }
+                Operation::Resize { scale } => write!(f, "resize {} ({})", scale, state),
+            }
+        }
+    }
+
+    impl PreprocessStep {
+        /// What is wrong with the step's parameters, if anything.
+        pub fn problem(&self) -> Option<String> {
+            match &self.operation {
+                Operation::Blur { kernel } if *kernel < 1 || kernel % 2 == 0 => {
+                    Some(format!("blur kernel {} must be a positive odd number", kernel))
+                }
+                Operation::Threshold { value: Some(value), .. } if !(0.0..=255.0).contains(value) => {
+                    Some(format!("threshold {} must be between 0 and 255", value))
+                }
+                Operation::Threshold { method, value: Some(_), .. } if *method != ThresholdMethod::Fixed => {
+                    Some(format!("threshold value only applies to the fixed method, not {:?}", method))
+                }
+                Operation::Threshold { c, .. } if !(0.0..=255.0).contains(c) => {
+                    Some(format!("threshold c {} must be between 0 and 255", c))
+                }
+                Operation::Threshold { method, block_size, .. }
+                    if matches!(method, ThresholdMethod::AdaptiveMean | ThresholdMethod::AdaptiveGaussian)
+                        && (*block_size < 3 || block_size % 2 == 0) =>
+                {
+                    Some(format!("threshold block_size {} must be an odd number of at least 3", block_size))
+                }
+                Operation::Morphology { kernel, .. } if *kernel < 1 => {
+                    Some(format!("morphology kernel {} must be positive", kernel))
+                }
+                Operation::Sharpen { amount } if !amount.is_finite() || *amount < 0.0 => {
+                    Some(format!("sharpen amount {} must not be negative", amount))
+                }
+                Operation::Clahe { clip_limit, tile_size }
+                    if !clip_limit.is_finite() || *clip_limit <= 0.0 || *tile_size < 1 =>
+                {
+                    Some("clahe clip_limit and tile_size must be positive".to_string())
+                }
+                Operation::Resize { scale } if !scale.is_finite() || *scale <= 0.0 => {
+                    Some(format!("resize scale {} must be positive", scale))
+                }
+                _ => None,
+            }
+        }
+
+        fn apply(&self, mat: Mat, default_threshold: f64) -> AppResult<Mat> {
+            let mut output = Mat::default();
+
+            match &self.operation {
+                Operation::Grayscale {} => return to_grayscale(mat),
+                Operation::Blur { kernel } => {
+                    opencv::imgproc::gaussian_blur(
+                        &mat,
+                        &mut output,
+                        Size::new(*kernel, *kernel),
+                        0.0,
+                        0.0,
+                        opencv::core::BORDER_DEFAULT,
+                        opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT,
+                    )?;
+                }
+                Operation::Threshold { method, value, block_size, c, text } => {
+                    // Inverting dark text keeps the output white on black either way
+                    let threshold_type = match text {
+                        TextColor::Light => opencv::imgproc::THRESH_BINARY,
+                        TextColor::Dark => opencv::imgproc::THRESH_BINARY_INV,
+                    };
+
+                    match method {
+                        ThresholdMethod::Fixed => {
+                            let value = value.unwrap_or(default_threshold);
+                            opencv::imgproc::threshold(&mat, &mut output, value, 255.0, threshold_type)?;
+                        }
+                        // Otsu and the adaptive methods only work on single channel images
+                        ThresholdMethod::Otsu => {
+                            opencv::imgproc::threshold(
+                                &to_grayscale(mat)?,
+                                &mut output,
+                                0.0,
+                                255.0,
+                                threshold_type | opencv::imgproc::THRESH_OTSU,
+                            )?;
+                        }
+                        ThresholdMethod::AdaptiveMean | ThresholdMethod::AdaptiveGaussian => {
+                            let adaptive_method = if *method == ThresholdMethod::AdaptiveMean {
+                                opencv::imgproc::ADAPTIVE_THRESH_MEAN_C
+                            } else {
+                                opencv::imgproc::ADAPTIVE_THRESH_GAUSSIAN_C
+                            };
+                            // OpenCV keeps pixels above the neighbourhood mean less `c`, so light
+                            // text needs a negative offset or a flat background turns white too
+                            let offset = match text {
+                                TextColor::Light => -c,
+                                TextColor::Dark => *c,
+                            };
+                            opencv::imgproc::adaptive_threshold(
+                                &to_grayscale(mat)?,
+                                &mut output,
+                                255.0,
+                                adaptive_method,
+                                threshold_type,
+                                *block_size,
+                                offset,
+                            )?;
+                        }
+                    }
+                }
+                Operation::Invert {} => {
+                    opencv::core::bitwise_not(&mat, &mut output, &opencv::core::no_array())?;
+                }
+                Operation::Morphology { operation, kernel } => {
+                    let operation = match operation {
+                        MorphologyOperation::Open => opencv::imgproc::MORPH_OPEN,
+                        MorphologyOperation::Close => opencv::imgproc::MORPH_CLOSE,
+                        MorphologyOperation::Dilate => opencv::imgproc::MORPH_DILATE,
+                        MorphologyOperation::Erode => opencv::imgproc::MORPH_ERODE,
+                    };
+                    let kernel = opencv::imgproc::get_structuring_element(
+                        opencv::imgproc::MORPH_RECT,
+                        Size::new(*kernel, *kernel),
+                        Point::new(-1, -1),
+                    )?;
+                    opencv::imgproc::morphology_ex(
+                        &mat,
+                        &mut output,
+                        operation,
+                        &kernel,
+                        Point::new(-1, -1),
+                        1,
+                        opencv::core::BORDER_CONSTANT,
+                        opencv::imgproc::morphology_default_border_value()?,
+                    )?;
+                }
+                Operation::Sharpen { amount } => {
+                    let mut blurred = Mat::default();
+                    opencv::imgproc::gaussian_blur(
+                        &mat,
+                        &mut blurred,
+                        Size::new(0, 0),
+                        3.0,
+                        3.0,
+                        opencv::core::BORDER_DEFAULT,
+                        opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT,
+                    )?;
+                    opencv::core::add_weighted(&mat, 1.0 + amount, &blurred, -amount, 0.0, &mut output, -1)?;
+                }
+                Operation::Clahe { clip_limit, tile_size } => {
+                    let gray = to_grayscale(mat)?;
+                    let mut clahe = opencv::imgproc::create_clahe(*clip_limit, Size::new(*tile_size, *tile_size))?;
+                    clahe.apply(&gray, &mut output)?;
+                }
+                Operation::Resize { scale } => {
+                    // Area averaging shrinks cleanly, cubic interpolation keeps glyphs smooth when enlarging
+                    let interpolation = if *scale < 1.0 {
+                        opencv::imgproc::INTER_AREA
+                    } else {
+                        opencv::imgproc::INTER_CUBIC
+                    };
+                    opencv::imgproc::resize(&mat, &mut output, Size::default(), *scale, *scale, interpolation)?;
+                }
+            }
+
+            Ok(output)
+        }
+    }
+
+    /// Runs the enabled steps over `mat` in order.
+    pub fn apply_steps(steps: &[PreprocessStep], mut mat: Mat, default_threshold: f64) -> AppResult<Mat> {
+        for step in steps.iter().filter(|step| step.enabled) {
+            mat = step.apply(mat, default_threshold)?;
+        }
+
+        Ok(mat)
+    }
+
+    fn to_grayscale(mat: Mat) -> AppResult<Mat> {
+        if mat.channels() == 1 {
+            return Ok(mat);
+        }
+
+        let mut gray = Mat::default();
+        opencv::imgproc::cvt_color(
+            &mat,
+            &mut gray,
+            opencv::imgproc::COLOR_BGR2GRAY,
+            0,
+            opencv::core::AlgorithmHint::ALGO_HINT_DEFAULT,
+        )?;
+        Ok(gray)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn step(toml: &str) -> Result<PreprocessStep, toml::de::Error> {
+            toml::from_str(toml)
+        }
+
+        fn gray(rows: &[[u8; 3]]) -> Mat {
+            Mat::from_slice_2d(rows).unwrap()
+        }
+
+        #[test]
+        fn reads_a_step_with_its_defaults() {
+            let blur = step("op = \"blur\"").unwrap();
+            assert!(blur.enabled);
+            assert!(matches!(blur.operation, Operation::Blur { kernel: 5 }));
+
+            let threshold = step("op = \"threshold\"\nmethod = \"adaptive_gaussian\"\ntext = \"dark\"\nenabled = false").unwrap();
+            assert!(!threshold.enabled);
+            assert!(matches!(
+                threshold.operation,
+                Operation::Threshold {
+                    method: ThresholdMethod::AdaptiveGaussian,
+                    value: None,
+                    block_size: 31,
+                    text: TextColor::Dark,
+                    ..
+                }
+            ));
+        }
+
+        #[test]
+        fn rejects_misspelled_steps() {
+            assert!(step("op = \"grayscale\"\nkernel = 3").is_err());
+            assert!(step("op = \"blur\"\nkernal = 3").is_err());
+            assert!(step("op = \"blurr\"").is_err());
+            assert!(step("op = \"invert\"\nenabled = \"yes\"").is_err());
+            assert!(step("op = \"resize\"").is_err());
+        }
+
+        #[test]
+        fn finds_problems_in_the_parameters() {
+            let problem = |toml: &str| step(toml).unwrap().problem();
+            assert_eq!(problem("op = \"blur\"\nkernel = 3"), None);
+            assert!(problem("op = \"blur\"\nkernel = 4").is_some());
+            assert!(problem("op = \"threshold\"\nvalue = 300.0").is_some());
+            assert!(problem("op = \"threshold\"\nmethod = \"otsu\"\nvalue = 100.0").is_some());
+            assert!(problem("op = \"threshold\"\nmethod = \"adaptive_mean\"\nblock_size = 4").is_some());
+            // The block size only matters to the adaptive methods
+            assert_eq!(problem("op = \"threshold\"\nblock_size = 4"), None);
+            assert!(problem("op = \"sharpen\"\namount = -1.0").is_some());
+            assert!(problem("op = \"clahe\"\ntile_size = 0").is_some());
+            assert!(problem("op = \"resize\"\nscale = 0.0").is_some());
+            assert!(default_steps().iter().all(|step| step.problem().is_none()));
+        }
+
+        #[test]
+        fn thresholds_light_and_dark_text() {
+            let image = || gray(&[[10, 200, 90], [130, 255, 0]]);
+
+            let light = step("op = \"threshold\"").unwrap().apply(image(), 128.0).unwrap();
+            assert_eq!(light.data_bytes().unwrap(), [0, 255, 0, 255, 255, 0]);
+
+            let dark = step("op = \"threshold\"\nvalue = 100.0\ntext = \"dark\"").unwrap().apply(image(), 128.0).unwrap();
+            assert_eq!(dark.data_bytes().unwrap(), [255, 0, 255, 0, 0, 255]);
+        }
+
+        #[test]
+        fn applies_only_the_enabled_steps() {
+            let steps = [
+                step("op = \"invert\"\nenabled = false").unwrap(),
+                step("op = \"resize\"\nscale = 2.0").unwrap(),
+                step("op = \"threshold\"").unwrap(),
+            ];
+
+            // Inverting would turn the dark left side white
+            let output = apply_steps(&steps, gray(&[[0, 0, 255], [0, 0, 255]]), 128.0).unwrap();
+            assert_eq!((output.rows(), output.cols()), (4, 6));
+            assert_eq!(*output.at_2d::<u8>(0, 0).unwrap(), 0);
+            assert_eq!(*output.at_2d::<u8>(3, 5).unwrap(), 255);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+pub mod providers {
+    use std::str::FromStr;
+    use async_trait::async_trait;
+    use serde::Deserialize;
+    use crate::error::error::AppResult;
+    use crate::utils::utils::{InterpretedLine, SynthesizedAudio, TextToSpeechLanguage, TranslationResponse, UsageOptions};
+
+    /// Which cloud service backs a pipeline stage.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Provider {
+        Google,
+        Azure,
+    }
+
+    impl FromStr for Provider {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_lowercase().as_str() {
+                "google" => Ok(Provider::Google),
+                "azure" => Ok(Provider::Azure),
+                other => Err(format!("Unknown provider '{}', expected 'google' or 'azure'", other)),
+            }
+        }
+    }
+
+    /// Extracts the lines of text shown in an encoded image, with where they were found.
+    #[async_trait(?Send)]
+    pub trait OcrEngine {
+        async fn recognize(
+            &self,
+            image: Vec<u8>,
+            usage_options: &UsageOptions,
+        ) -> AppResult<Vec<InterpretedLine>>;
+    }
+
+    /// Translates Japanese text into the requested output languages.
+    #[async_trait(?Send)]
+    pub trait Translator {
+        async fn translate(
+            &self,
+            text: &str,
+            output_languages: &[TextToSpeechLanguage],
+        ) -> AppResult<TranslationResponse>;
+    }
+
+    /// Turns text into speech in the given language, returning the audio in memory.
+    #[async_trait(?Send)]
+    pub trait SpeechSynthesizer {
+        async fn synthesize(
+            &self,
+            text: &str,
+            language: TextToSpeechLanguage,
+        ) -> AppResult<SynthesizedAudio>;
+    }
+}
@@ -0,0 +1,233 @@
+pub mod regions {
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+    use opencv::core::Rect;
+    use serde::{Deserialize, Serialize};
+    use crate::error::error::{AppError, AppResult};
+
+    /// Whether a region is given in pixels or as fractions of the frame size.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum RegionUnit {
+        #[default]
+        Fraction,
+        Pixels,
+    }
+
+    /// A rectangle of the frame that is cropped to before OCR.
+    #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Region {
+        #[serde(default)]
+        pub unit: RegionUnit,
+        pub x: f64,
+        pub y: f64,
+        pub width: f64,
+        pub height: f64,
+    }
+
+    impl Region {
+        /// The part of a `frame_width` x `frame_height` frame covered by the region, clipped
+        /// to the frame. `None` when nothing of the region is inside the frame.
+        pub fn to_rect(&self, frame_width: i32, frame_height: i32) -> Option<Rect> {
+            let (scale_x, scale_y) = match self.unit {
+                RegionUnit::Fraction => (frame_width as f64, frame_height as f64),
+                RegionUnit::Pixels => (1.0, 1.0),
+            };
+            let clip = |value: f64, max: i32| (value.round() as i32).clamp(0, max);
+
+            let left = clip(self.x * scale_x, frame_width);
+            let top = clip(self.y * scale_y, frame_height);
+            let right = clip((self.x + self.width) * scale_x, frame_width);
+            let bottom = clip((self.y + self.height) * scale_y, frame_height);
+
+            (right > left && bottom > top).then(|| Rect::new(left, top, right - left, bottom - top))
+        }
+
+        /// What is wrong with the region, if anything.
+        pub fn problem(&self) -> Option<String> {
+            if self.width <= 0.0 || self.height <= 0.0 {
+                return Some("must have a positive width and height".to_string());
+            }
+            if self.x < 0.0 || self.y < 0.0 {
+                return Some("must not start outside the frame".to_string());
+            }
+            if self.unit == RegionUnit::Fraction && (self.x + self.width > 1.0 || self.y + self.height > 1.0) {
+                return Some("must lie within the frame, using fractions between 0 and 1".to_string());
+            }
+
+            None
+        }
+    }
+
+    /// Parses `x,y,width,height` as fractions, or as pixels with a `px` suffix,
+    /// e.g. `0,0.6,1,0.4` or `120,700,1600,300px`.
+    impl FromStr for Region {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let s = s.trim();
+            let (values, unit) = match s.strip_suffix("px") {
+                Some(values) => (values, RegionUnit::Pixels),
+                None => (s, RegionUnit::Fraction),
+            };
+
+            let values = values
+                .split(',')
+                .map(|value| value.trim().parse::<f64>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("Invalid region '{}': {}", s, e))?;
+
+            let [x, y, width, height] = values[..] else {
+                return Err(format!("Invalid region '{}', expected x,y,width,height", s));
+            };
+
+            let region = Region { unit, x, y, width, height };
+            match region.problem() {
+                Some(problem) => Err(format!("Region '{}' {}", s, problem)),
+                None => Ok(region),
+            }
+        }
+    }
+
+    /// Named regions of interest, e.g. "dialogue", "menu" or "name_plate", and the one
+    /// frames are currently cropped to. Per-game profiles are saved in this shape.
+    #[derive(Clone, Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct Profile {
+        pub active: String,
+        #[serde(default)]
+        pub named: BTreeMap<String, Region>,
+    }
+
+    impl Profile {
+        pub fn active_region(&self) -> Option<&Region> {
+            self.named.get(&self.active)
+        }
+
+        pub fn select(&mut self, name: &str) -> AppResult<()> {
+            if !self.named.contains_key(name) {
+                return Err(AppError::Config(format!(
+                    "No region named '{}', expected one of: {}",
+                    name,
+                    self.names().join(", ")
+                )));
+            }
+
+            self.active = name.to_string();
+            Ok(())
+        }
+
+        pub fn names(&self) -> Vec<&str> {
+            self.named.keys().map(String::as_str).collect()
+        }
+
+        /// Takes over the regions and active region of `other`, keeping regions it does
+        /// not mention.
+        pub fn merge(&mut self, other: Profile) {
+            self.named.extend(other.named);
+            self.active = other.active;
+        }
+
+        pub fn problems(&self) -> Vec<String> {
+            let mut problems = self
+                .named
+                .iter()
+                .filter_map(|(name, region)| region.problem().map(|problem| format!("region '{}' {}", name, problem)))
+                .collect::<Vec<_>>();
+
+            if !self.named.contains_key(&self.active) {
+                problems.push(format!("active region '{}' is not one of the named regions", self.active));
+            }
+
+            problems
+        }
+
+        /// Reads `<directory>/<name>.toml`.
+        pub fn load(directory: &Path, name: &str) -> AppResult<Self> {
+            let path = profile_path(directory, name)?;
+            let contents = std::fs::read_to_string(&path).map_err(|e| {
+                AppError::Config(format!("Could not read profile {}: {}", path.display(), e))
+            })?;
+
+            toml::from_str(&contents)
+                .map_err(|e| AppError::Config(format!("Invalid profile {}: {}", path.display(), e)))
+        }
+
+        /// Writes the profile to `<directory>/<name>.toml`, returning the path.
+        pub fn save(&self, directory: &Path, name: &str) -> AppResult<PathBuf> {
+            let contents = toml::to_string_pretty(self)
+                .map_err(|e| AppError::Config(format!("Could not serialize profile: {}", e)))?;
+
+            let path = profile_path(directory, name)?;
+            std::fs::create_dir_all(directory)?;
+            std::fs::write(&path, contents)?;
+
+            Ok(path)
+        }
+    }
+
+    /// The file of the profile `name`, which has to stay inside `directory`.
+    fn profile_path(directory: &Path, name: &str) -> AppResult<PathBuf> {
+        let invalid = name.trim().is_empty()
+            || name.starts_with('.')
+            || name.contains(['/', '\\', ':'])
+            || name.contains("..");
+        if invalid {
+            return Err(AppError::Config(format!(
+                "Invalid profile name '{}', it must not be a path",
+                name
+            )));
+        }
+
+        Ok(directory.join(format!("{}.toml", name)))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn parses_fractions_and_pixels() {
+            let region: Region = " 0, 0.6, 1, 0.4 ".parse().unwrap();
+            assert_eq!(region.unit, RegionUnit::Fraction);
+            assert_eq!((region.x, region.y, region.width, region.height), (0.0, 0.6, 1.0, 0.4));
+
+            let region: Region = "120,700,1600,300px".parse().unwrap();
+            assert_eq!(region.unit, RegionUnit::Pixels);
+            assert_eq!((region.x, region.y, region.width, region.height), (120.0, 700.0, 1600.0, 300.0));
+        }
+
+        #[test]
+        fn rejects_invalid_regions() {
+            for region in ["", "0,0,1", "0,0,1,1,1", "a,0,1,1", "0,0,0,1", "-1,0,10,10px", "0.5,0,0.6,1"] {
+                assert!(region.parse::<Region>().is_err(), "{} should not parse", region);
+            }
+        }
+
+        #[test]
+        fn scales_fractions_to_the_frame() {
+            let region: Region = "0,0.6,1,0.4".parse().unwrap();
+            assert_eq!(region.to_rect(1920, 1080), Some(Rect::new(0, 648, 1920, 432)));
+        }
+
+        #[test]
+        fn clips_pixels_to_the_frame() {
+            let region: Region = "100,900,400,300px".parse().unwrap();
+            assert_eq!(region.to_rect(1920, 1080), Some(Rect::new(100, 900, 400, 180)));
+
+            let outside: Region = "2000,0,100,100px".parse().unwrap();
+            assert_eq!(outside.to_rect(1920, 1080), None);
+        }
+
+        #[test]
+        fn keeps_profiles_inside_their_directory() {
+            let directory = Path::new("profiles");
+            assert_eq!(profile_path(directory, "persona5").unwrap(), directory.join("persona5.toml"));
+            for name in ["", " ", ".hidden", "../config", "games/persona5", "C:persona5", "a\\b"] {
+                assert!(profile_path(directory, name).is_err(), "{:?} should be rejected", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+pub mod report {
+    use std::path::Path;
+    use serde::Serialize;
+    use crate::error::error::AppResult;
+
+    /// One row of a report file, the result of an image in a batch or of a frame in a video.
+    pub trait ReportRow: Serialize {
+        /// The names of the CSV columns, in the order `record` gives them.
+        const HEADER: &'static [&'static str];
+
+        fn record(&self) -> Vec<&str>;
+    }
+
+    /// Writes the rows as CSV when `path` ends in `.csv`, a JSON array otherwise.
+    pub fn write_report<T: ReportRow>(path: &Path, rows: &[T]) -> AppResult<()> {
+        let is_csv = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+
+        if !is_csv {
+            let file = std::fs::File::create(path)?;
+            return Ok(serde_json::to_writer_pretty(file, rows)?);
+        }
+
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record(T::HEADER)?;
+        for row in rows {
+            writer.write_record(row.record())?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+}
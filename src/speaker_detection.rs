@@ -0,0 +1,250 @@
+pub mod speaker_detection {
+    use opencv::core::{Mat, Rect, Scalar};
+    use opencv::prelude::MatTraitConst;
+    use crate::config::config::SpeakerDetectionConfig;
+    use crate::error::error::AppResult;
+    use crate::utils::utils::{DialogueLine, InterpretedLine, TextBox};
+
+    // Brackets that enclose a line of speech in Japanese dialogue
+    const SPEECH_BRACKETS: [(char, char); 2] = [('「', '」'), ('『', '』')];
+    // Glyph strokes cover only part of a line's box, this much of it must have the name colour
+    const MIN_COLOR_SHARE: f64 = 0.05;
+
+    /// Turns the lines OCR found into dialogue, telling the speaker's name apart from what
+    /// is said with the heuristics enabled in the configuration.
+    pub struct SpeakerDetector {
+        config: SpeakerDetectionConfig,
+    }
+
+    impl SpeakerDetector {
+        pub fn new(config: &SpeakerDetectionConfig) -> Self {
+            Self {
+                config: config.clone(),
+            }
+        }
+
+        /// Whether `detect` looks at the image the lines were read from.
+        pub fn needs_image(&self) -> bool {
+            self.config.enabled && self.config.name_color.is_some()
+        }
+
+        /// Puts the lines in reading order and splits off the speaker's name, if there is
+        /// one. `image` is only needed for the colour heuristic.
+        pub fn detect(&self, mut lines: Vec<InterpretedLine>, image: Option<&Mat>) -> AppResult<DialogueLine> {
+            lines.sort();
+
+            let (speaker, text) = if self.config.enabled {
+                self.split_speaker(&lines, image)?
+            } else {
+                (None, join(&lines))
+            };
+
+            let confidences: Vec<f32> = lines.iter().filter_map(|line| line.confidence).collect();
+            let confidence = (!confidences.is_empty())
+                .then(|| confidences.iter().sum::<f32>() / confidences.len() as f32);
+
+            Ok(DialogueLine {
+                speaker,
+                text,
+                boxes: lines.iter().map(TextBox::from).collect(),
+                confidence,
+            })
+        }
+
+        fn split_speaker(
+            &self,
+            lines: &[InterpretedLine],
+            image: Option<&Mat>,
+        ) -> AppResult<(Option<String>, String)> {
+            let Some((first, rest)) = lines.split_first() else {
+                return Ok((None, String::new()));
+            };
+
+            // The name and the speech on one line, as in 名前「……」. Narration that quotes
+            // someone, as in 彼は「はい」と言った, does not end with the bracket
+            if self.config.brackets {
+                if let Some(index) = first.text.find(is_opening_bracket) {
+                    let name = &first.text[..index];
+                    let mut text = first.text[index..].to_string();
+                    append(&mut text, &join(rest));
+                    if self.is_name(name) && is_speech(&text) {
+                        return Ok((Some(name_of(name)), text));
+                    }
+                }
+            }
+
+            if rest.is_empty() || !self.is_name(&first.text) {
+                return Ok((None, join(lines)));
+            }
+
+            let config = &self.config;
+            let indented = config.indent > 0 && rest.iter().all(|line| first.x - line.x > config.indent);
+            let on_name_plate = config.name_plate && rest.iter().all(|line| line.block != first.block);
+            let before_bracket = config.brackets && is_speech(&join(rest));
+            let colored = match (config.name_color, image) {
+                (Some(color), Some(image)) => {
+                    has_color(image, first, color, config.color_tolerance)?
+                        && !has_color(image, &rest[0], color, config.color_tolerance)?
+                }
+                _ => false,
+            };
+
+            if indented || on_name_plate || before_bracket || colored {
+                Ok((Some(name_of(&first.text)), join(rest)))
+            } else {
+                Ok((None, join(lines)))
+            }
+        }
+
+        /// Whether `text` is short enough for a name and does not read like a sentence.
+        fn is_name(&self, text: &str) -> bool {
+            let name = name_of(text);
+            let length = name.chars().count();
+            length > 0
+                && length <= self.config.max_name_length
+                && !name.contains(['。', '、', '！', '？', '…', '「', '『'])
+        }
+    }
+
+    /// The name without the colon or 【】 brackets that set it off.
+    fn name_of(text: &str) -> String {
+        text.trim()
+            .trim_end_matches([':', '：'])
+            .trim_start_matches('【')
+            .trim_end_matches('】')
+            .trim()
+            .to_string()
+    }
+
+    fn is_opening_bracket(c: char) -> bool {
+        SPEECH_BRACKETS.iter().any(|(open, _)| *open == c)
+    }
+
+    /// Whether `text` is one bracketed line of speech, 「……」 or 『……』.
+    fn is_speech(text: &str) -> bool {
+        let text = text.trim();
+        text.chars().count() > 1
+            && SPEECH_BRACKETS
+                .iter()
+                .any(|(open, close)| text.starts_with(*open) && text.ends_with(*close))
+    }
+
+    fn join(lines: &[InterpretedLine]) -> String {
+        let mut text = String::new();
+        for line in lines {
+            append(&mut text, &line.text);
+        }
+        text
+    }
+
+    /// Adds the next line to `text`. Japanese runs on from line to line, other scripts get
+    /// a space in between, except after a hyphen that breaks a word.
+    fn append(text: &mut String, line: &str) {
+        let line = line.trim();
+        if let (Some(last), Some(next)) = (text.chars().last(), line.chars().next()) {
+            if last != '-' && !is_cjk(last) && !is_cjk(next) {
+                text.push(' ');
+            }
+        }
+        text.push_str(line);
+    }
+
+    /// Whether `c` is written without spaces around it: kana, CJK ideographs and the
+    /// punctuation and full-width forms that go with them.
+    fn is_cjk(c: char) -> bool {
+        matches!(
+            c,
+            '\u{3000}'..='\u{30FF}'
+                | '\u{3400}'..='\u{4DBF}'
+                | '\u{4E00}'..='\u{9FFF}'
+                | '\u{F900}'..='\u{FAFF}'
+                | '\u{FF00}'..='\u{FFEF}'
+        )
+    }
+
+    /// Whether enough of the line's box has the colour `[r, g, b]`, give or take `tolerance`.
+    fn has_color(image: &Mat, line: &InterpretedLine, [r, g, b]: [u8; 3], tolerance: f64) -> AppResult<bool> {
+        let left = line.x.max(0);
+        let top = line.y.max(0);
+        let right = (line.x + line.width).min(image.cols());
+        let bottom = (line.y + line.height).min(image.rows());
+        if right <= left || bottom <= top || image.channels() != 3 {
+            return Ok(false);
+        }
+
+        let area = Rect::new(left, top, right - left, bottom - top);
+        let roi = image.roi(area)?;
+
+        // OpenCV keeps the channels in BGR order
+        let [b, g, r] = [b, g, r].map(f64::from);
+        let lower = Scalar::new(b - tolerance, g - tolerance, r - tolerance, 0.0);
+        let upper = Scalar::new(b + tolerance, g + tolerance, r + tolerance, 0.0);
+        let mut mask = Mat::default();
+        opencv::core::in_range(&roi, &lower, &upper, &mut mask)?;
+
+        let share = opencv::core::count_non_zero(&mask)? as f64 / area.area() as f64;
+        Ok(share >= MIN_COLOR_SHARE)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn line(x: i32, y: i32, text: &str) -> InterpretedLine {
+            let mut line = InterpretedLine::new(x, y, 400, 30);
+            line.text.push_str(text);
+            line
+        }
+
+        fn split(lines: Vec<InterpretedLine>) -> (Option<String>, String) {
+            let detector = SpeakerDetector::new(&SpeakerDetectionConfig::default());
+            detector.split_speaker(&lines, None).unwrap()
+        }
+
+        fn speaker(name: &str, text: &str) -> (Option<String>, String) {
+            (Some(name.to_string()), text.to_string())
+        }
+
+        #[test]
+        fn splits_a_name_in_front_of_the_brackets() {
+            let lines = vec![line(0, 0, "竜司「マジかよ"), line(0, 40, "ありえねえ」")];
+            assert_eq!(split(lines), speaker("竜司", "「マジかよありえねえ」"));
+        }
+
+        #[test]
+        fn keeps_quotes_inside_narration() {
+            assert_eq!(split(vec![line(0, 0, "彼は「はい」と言った")]), (None, "彼は「はい」と言った".to_string()));
+        }
+
+        #[test]
+        fn takes_the_line_before_speech_as_the_name() {
+            let lines = vec![line(0, 0, "【モルガナ】"), line(0, 40, "『急げ！』")];
+            assert_eq!(split(lines), speaker("モルガナ", "『急げ！』"));
+        }
+
+        #[test]
+        fn takes_an_indented_line_as_the_name() {
+            assert_eq!(split(vec![line(200, 0, "杏："), line(100, 40, "行こう。")]), speaker("杏", "行こう。"));
+            assert_eq!(split(vec![line(100, 0, "杏"), line(100, 40, "行こう。")]), (None, "杏行こう。".to_string()));
+        }
+
+        #[test]
+        fn does_not_take_a_sentence_as_the_name() {
+            let lines = vec![line(0, 0, "いい天気だね。"), line(0, 40, "「そうだね」")];
+            assert_eq!(split(lines), (None, "いい天気だね。「そうだね」".to_string()));
+        }
+
+        #[test]
+        fn joins_latin_lines_with_spaces() {
+            let detector = SpeakerDetector::new(&SpeakerDetectionConfig {
+                enabled: false,
+                ..SpeakerDetectionConfig::default()
+            });
+            let lines = vec![line(0, 80, "tion."), line(0, 0, "Hello, world"), line(0, 40, "transla-")];
+
+            let dialogue = detector.detect(lines, None).unwrap();
+            assert_eq!(dialogue.speaker, None);
+            assert_eq!(dialogue.text, "Hello, world transla-tion.");
+        }
+    }
+}
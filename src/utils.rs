@@ -2,16 +2,35 @@ pub mod utils {
     use std::cmp::Ordering;
     use std::fmt::Display;
     use std::str::FromStr;
+    use serde::{Deserialize, Serialize};
     use TextToSpeechLanguage::{Japanese, English, Finnish, Swedish};
 
-    #[derive(PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 pub enum TextToSpeechLanguage {
+        #[serde(rename = "ja")]
         Japanese,
+        #[serde(rename = "en")]
         English,
+        #[serde(rename = "fi")]
         Finnish,
+        #[serde(rename = "sv")]
         Swedish,
     }
 
+    impl FromStr for TextToSpeechLanguage {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_lowercase().as_str() {
+                "ja" => Ok(Japanese),
+                "en" => Ok(English),
+                "fi" => Ok(Finnish),
+                "sv" => Ok(Swedish),
+                other => Err(format!("Unknown language '{}', expected ja, en, fi or sv", other)),
+            }
+        }
+    }
+
     impl Display for TextToSpeechLanguage {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             match self {
@@ -23,12 +42,58 @@ pub enum TextToSpeechLanguage {
         }
     }
 
+    /// The locale a voice belongs to, e.g. "ja-JP" for "ja-JP-NanamiNeural".
+    pub fn voice_locale(voice: &str) -> &str {
+        voice
+            .match_indices('-')
+            .nth(1)
+            .map_or(voice, |(index, _)| &voice[..index])
+    }
+
+    #[derive(Serialize, Default)]
     pub struct TranslationResponse {
+        #[serde(rename = "en", skip_serializing_if = "String::is_empty")]
         pub en_translation: String,
+        #[serde(rename = "fi", skip_serializing_if = "String::is_empty")]
         pub fi_translation: String,
+        #[serde(rename = "sv", skip_serializing_if = "String::is_empty")]
         pub sv_translation: String,
     }
 
+    impl TranslationResponse {
+        /// The translation into `language`, empty when there is none.
+        pub fn get(&self, language: TextToSpeechLanguage) -> &str {
+            match language {
+                English => &self.en_translation,
+                Finnish => &self.fi_translation,
+                Swedish => &self.sv_translation,
+                Japanese => "",
+            }
+        }
+
+        pub fn set(&mut self, language: TextToSpeechLanguage, translation: String) {
+            match language {
+                English => self.en_translation = translation,
+                Finnish => self.fi_translation = translation,
+                Swedish => self.sv_translation = translation,
+                Japanese => {}
+            }
+        }
+    }
+
+    /// Encoding of a synthesized audio clip.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum AudioCodec {
+        Mp3,
+    }
+
+    /// Audio returned by a text-to-speech provider, kept in memory until played.
+    pub struct SynthesizedAudio {
+        pub data: Vec<u8>,
+        pub codec: AudioCodec,
+        pub sample_rate: u32,
+    }
+
     pub struct UsageOptions {
         pub playback_en: bool,
         pub playback_fi: bool,
@@ -36,17 +101,22 @@ pub enum TextToSpeechLanguage {
         pub translate_en: bool,
         pub translate_fi: bool,
         pub translate_sv: bool,
-        pub half_screen: bool,
+        pub use_region: bool,
+        pub detect_dialogue: bool,
         pub debug_printing: bool,
         pub color_correction: bool,
     }
 
-    #[derive(PartialEq, Eq)]
+    /// A line of recognized text with its bounding box in the image. `block` numbers the
+    /// text block the line belongs to, and `confidence` (0-1) is given by providers that
+    /// report one.
     pub struct InterpretedLine {
         pub x: i32,
         pub y: i32,
         pub width: i32,
         pub height: i32,
+        pub block: usize,
+        pub confidence: Option<f32>,
         pub text: String,
     }
 
@@ -57,11 +127,23 @@ pub enum TextToSpeechLanguage {
                 y,
                 width,
                 height,
+                block: 0,
+                confidence: None,
                 text: String::with_capacity(50),
             }
         }
     }
 
+    impl Display for InterpretedLine {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{},{} {}x{} block {}", self.x, self.y, self.width, self.height, self.block)?;
+            if let Some(confidence) = self.confidence {
+                write!(f, " confidence {:.2}", confidence)?;
+            }
+            write!(f, ": {}", self.text)
+        }
+    }
+
     impl FromStr for InterpretedLine {
         type Err = ();
 
@@ -109,4 +191,55 @@ pub enum TextToSpeechLanguage {
             Some(self.cmp(other))
         }
     }
-}
\ No newline at end of file
+
+    // Lines compare by position only, in agreement with the ordering
+    impl PartialEq for InterpretedLine {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl Eq for InterpretedLine {}
+
+    /// Where a line of text was found in the image sent to OCR.
+    #[derive(Debug, Clone, Copy, Serialize)]
+    pub struct TextBox {
+        pub x: i32,
+        pub y: i32,
+        pub width: i32,
+        pub height: i32,
+    }
+
+    impl From<&InterpretedLine> for TextBox {
+        fn from(line: &InterpretedLine) -> Self {
+            Self {
+                x: line.x,
+                y: line.y,
+                width: line.width,
+                height: line.height,
+            }
+        }
+    }
+
+    /// What a capture says: the dialogue and, when one was recognized, who says it.
+    /// `boxes` are the lines it was read from and `confidence` (0-1) is their average
+    /// where the provider reports one.
+    #[derive(Debug, Clone, Default, Serialize)]
+    pub struct DialogueLine {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub speaker: Option<String>,
+        pub text: String,
+        pub boxes: Vec<TextBox>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub confidence: Option<f32>,
+    }
+
+    impl Display for DialogueLine {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match &self.speaker {
+                Some(speaker) => write!(f, "{}: {}", speaker, self.text),
+                None => f.write_str(&self.text),
+            }
+        }
+    }
+}
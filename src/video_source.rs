@@ -0,0 +1,351 @@
+pub mod video_source {
+    use std::path::Path;
+    use std::str::FromStr;
+    use std::time::Duration;
+    use opencv::core::Mat;
+    use opencv::prelude::{MatTraitConst, VideoCaptureTrait, VideoCaptureTraitConst};
+    use opencv::videoio::VideoCapture;
+    use serde::{Deserialize, Serialize};
+    use crate::camera_capture::camera_capture::FrameProcessor;
+    use crate::change_detection::change_detection::{mean_difference, thumbnail};
+    use crate::cli::cli::{print_translations, OutputFormat};
+    use crate::config::config::VideoConfig;
+    use crate::error::error::{with_retries, AppError, AppResult};
+    use crate::frame_source::frame_source::FrameSource;
+    use crate::pipeline::pipeline::Pipeline;
+    use crate::regions::regions::Region;
+    use crate::report::report::ReportRow;
+    use crate::utils::utils::{DialogueLine, TextToSpeechLanguage, TranslationResponse, UsageOptions};
+
+    // How often frames are compared when sampling on scene changes
+    const SCENE_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Which frames of a video are sent to OCR.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Sampling {
+        /// One frame every `video.interval` seconds.
+        Interval,
+        /// A frame whenever the picture has changed and settled again, e.g. a new dialogue box.
+        SceneChange,
+    }
+
+    impl FromStr for Sampling {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.trim().to_lowercase().as_str() {
+                "interval" => Ok(Sampling::Interval),
+                "scene_change" | "scene-change" => Ok(Sampling::SceneChange),
+                other => Err(format!("Unknown sampling '{}', expected interval or scene_change", other)),
+            }
+        }
+    }
+
+    pub struct VideoFrame {
+        pub timestamp: Duration,
+        pub mat: Mat,
+    }
+
+    /// Which positions in the video are looked at: one every `video.interval` seconds, or
+    /// every `SCENE_CHECK_INTERVAL` when sampling on scene changes.
+    struct Schedule {
+        step: Duration,
+        next_check: Duration,
+    }
+
+    impl Schedule {
+        fn new(config: &VideoConfig) -> Self {
+            let step = match config.sampling {
+                Sampling::Interval => Duration::from_secs_f64(config.interval),
+                Sampling::SceneChange => SCENE_CHECK_INTERVAL,
+            };
+
+            Self {
+                step,
+                next_check: Duration::ZERO,
+            }
+        }
+
+        /// Whether the frame at `timestamp` is looked at. The next one is due a step later.
+        fn is_due(&mut self, timestamp: Duration) -> bool {
+            if timestamp < self.next_check {
+                return false;
+            }
+            self.next_check = timestamp + self.step;
+            true
+        }
+    }
+
+    /// Reads frames from a recorded video file through OpenCV's file backends.
+    pub struct VideoSource {
+        cap: VideoCapture,
+        sampling: Sampling,
+        schedule: Schedule,
+        threshold: f64,
+        previous_check: Option<Mat>,
+        last_sample: Option<Mat>,
+    }
+
+    impl VideoSource {
+        pub fn open(path: &Path, config: &VideoConfig) -> AppResult<Self> {
+            let cap = VideoCapture::from_file(&path.to_string_lossy(), opencv::videoio::CAP_ANY)?;
+            if !cap.is_opened()? {
+                return Err(AppError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Could not open video {}", path.display()),
+                )));
+            }
+
+            Ok(Self {
+                cap,
+                sampling: config.sampling,
+                schedule: Schedule::new(config),
+                threshold: config.scene_change_threshold,
+                previous_check: None,
+                last_sample: None,
+            })
+        }
+
+        /// The next sampled frame, or `None` at the end of the video.
+        pub fn next_sample(&mut self) -> AppResult<Option<VideoFrame>> {
+            loop {
+                // grab() skips frames without the cost of converting them
+                if !self.cap.grab()? {
+                    return Ok(None);
+                }
+
+                let position = self.cap.get(opencv::videoio::CAP_PROP_POS_MSEC)?.max(0.0);
+                let timestamp = Duration::from_secs_f64(position / 1000.0);
+                if !self.schedule.is_due(timestamp) {
+                    continue;
+                }
+
+                let mut mat = Mat::default();
+                if !self.cap.retrieve(&mut mat, 0)? || mat.empty() {
+                    continue;
+                }
+
+                if self.sampling == Sampling::Interval || self.is_new_scene(&mat)? {
+                    return Ok(Some(VideoFrame { timestamp, mat }));
+                }
+            }
+        }
+
+        /// True once the picture differs from the last sample and has stopped changing,
+        /// so text that is still being typed out is not sampled halfway.
+        fn is_new_scene(&mut self, mat: &Mat) -> AppResult<bool> {
+            let current = thumbnail(mat)?;
+
+            let settled = match &self.previous_check {
+                Some(previous) => mean_difference(previous, &current)? <= self.threshold,
+                None => true,
+            };
+            let changed = match &self.last_sample {
+                Some(last_sample) => mean_difference(last_sample, &current)? > self.threshold,
+                None => true,
+            };
+
+            self.previous_check = Some(current.clone());
+            if changed && settled {
+                self.last_sample = Some(current);
+            }
+
+            Ok(changed && settled)
+        }
+    }
+
+    impl FrameSource for VideoSource {
+        fn next_frame(&mut self) -> AppResult<Option<Mat>> {
+            Ok(self.next_sample()?.map(|frame| frame.mat))
+        }
+
+        fn describe(&self) -> String {
+            format!("video at {}", format_timestamp(self.schedule.next_check))
+        }
+    }
+
+    /// One line of dialogue and when it first appeared in the video. Frames that could not
+    /// be read or translated are recorded with the error instead of ending the transcript.
+    #[derive(Serialize)]
+    pub struct TranscriptEntry {
+        pub timestamp: String,
+        pub seconds: f64,
+        #[serde(flatten)]
+        pub line: DialogueLine,
+        pub translations: TranslationResponse,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub error: Option<String>,
+    }
+
+    /// Runs every sampled frame through OCR and translation, printing each new line of
+    /// text as it is found. Frames showing the same text as the previous one are skipped.
+    pub async fn transcribe(
+        source: &mut VideoSource,
+        processor: &FrameProcessor,
+        region: Option<&Region>,
+        pipeline: &Pipeline,
+        usage_options: &UsageOptions,
+        languages: &[TextToSpeechLanguage],
+        format: OutputFormat,
+    ) -> AppResult<Vec<TranscriptEntry>> {
+        let mut transcript = Vec::new();
+        let mut previous_text = String::new();
+
+        while let Some(frame) = source.next_sample()? {
+            let mut entry = TranscriptEntry {
+                timestamp: format_timestamp(frame.timestamp),
+                seconds: frame.timestamp.as_secs_f64(),
+                line: DialogueLine::default(),
+                translations: TranslationResponse::default(),
+                error: None,
+            };
+
+            match recognize(frame.mat, processor, region, pipeline, usage_options).await {
+                Ok(line) => entry.line = line,
+                Err(e) => {
+                    entry.error = Some(e.to_string());
+                    print_entry(&entry, format)?;
+                    transcript.push(entry);
+                    continue;
+                }
+            }
+
+            let text = entry.line.to_string();
+            if entry.line.text.is_empty() || text == previous_text {
+                continue;
+            }
+
+            let line = &entry.line;
+            match with_retries(move || pipeline.translate_dialogue(line, languages)).await {
+                Ok(translations) => entry.translations = translations,
+                Err(e) => entry.error = Some(e.to_string()),
+            }
+            print_entry(&entry, format)?;
+
+            transcript.push(entry);
+            previous_text = text;
+        }
+
+        Ok(transcript)
+    }
+
+    async fn recognize(
+        mat: Mat,
+        processor: &FrameProcessor,
+        region: Option<&Region>,
+        pipeline: &Pipeline,
+        usage_options: &UsageOptions,
+    ) -> AppResult<DialogueLine> {
+        let image = processor.process(mat, region, usage_options)?;
+
+        let image = &image;
+        let mut line = with_retries(move || pipeline.recognize(image.clone(), usage_options)).await?;
+        line.text = line.text.trim().to_string();
+        Ok(line)
+    }
+
+    fn print_entry(entry: &TranscriptEntry, format: OutputFormat) -> AppResult<()> {
+        match format {
+            OutputFormat::Json => println!("{}", serde_json::to_string(entry)?),
+            OutputFormat::Text => {
+                if let Some(error) = &entry.error {
+                    println!("[{}] {}\n", entry.timestamp, error);
+                    if entry.line.text.is_empty() {
+                        return Ok(());
+                    }
+                }
+                println!("[{}] {}\n", entry.timestamp, entry.line);
+                print_translations(&entry.translations);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Formats a position in the video as `hh:mm:ss.mmm`.
+    fn format_timestamp(timestamp: Duration) -> String {
+        let seconds = timestamp.as_secs();
+        format!(
+            "{:02}:{:02}:{:02}.{:03}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            timestamp.subsec_millis()
+        )
+    }
+
+    impl ReportRow for TranscriptEntry {
+        const HEADER: &'static [&'static str] = &["timestamp", "speaker", "text", "en", "fi", "sv", "error"];
+
+        fn record(&self) -> Vec<&str> {
+            vec![
+                self.timestamp.as_str(),
+                self.line.speaker.as_deref().unwrap_or_default(),
+                self.line.text.as_str(),
+                self.translations.en_translation.as_str(),
+                self.translations.fi_translation.as_str(),
+                self.translations.sv_translation.as_str(),
+                self.error.as_deref().unwrap_or_default(),
+            ]
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// The timestamps of a 25 fps video up to `seconds` that the schedule looks at.
+        fn due_frames(config: &VideoConfig, seconds: u64) -> Vec<u64> {
+            let mut schedule = Schedule::new(config);
+            (0..seconds * 25)
+                .map(|frame| frame * 40)
+                .filter(|&millis| schedule.is_due(Duration::from_millis(millis)))
+                .collect()
+        }
+
+        #[test]
+        fn parses_the_sampling() {
+            assert_eq!("interval".parse(), Ok(Sampling::Interval));
+            assert_eq!(" Scene-Change ".parse(), Ok(Sampling::SceneChange));
+            assert_eq!("scene_change".parse(), Ok(Sampling::SceneChange));
+            assert!("keyframes".parse::<Sampling>().is_err());
+
+            let config: VideoConfig = toml::from_str("sampling = \"scene_change\"").unwrap();
+            assert_eq!(config.sampling, Sampling::SceneChange);
+            assert!(toml::from_str::<VideoConfig>("sampling = \"scene-change\"").is_err());
+        }
+
+        #[test]
+        fn samples_one_frame_per_interval() {
+            let config = VideoConfig {
+                sampling: Sampling::Interval,
+                interval: 2.0,
+                ..VideoConfig::default()
+            };
+            assert_eq!(due_frames(&config, 7), [0, 2000, 4000, 6000]);
+
+            // Frames rarely fall on the interval exactly, the next is counted from the one taken
+            let config = VideoConfig {
+                interval: 0.5,
+                ..config
+            };
+            assert_eq!(due_frames(&config, 2), [0, 520, 1040, 1560]);
+        }
+
+        #[test]
+        fn checks_for_scene_changes_every_quarter_second() {
+            let config = VideoConfig {
+                sampling: Sampling::SceneChange,
+                ..VideoConfig::default()
+            };
+            assert_eq!(due_frames(&config, 1), [0, 280, 560, 840]);
+        }
+
+        #[test]
+        fn formats_timestamps() {
+            assert_eq!(format_timestamp(Duration::ZERO), "00:00:00.000");
+            assert_eq!(format_timestamp(Duration::from_millis(3_723_456)), "01:02:03.456");
+        }
+    }
+}